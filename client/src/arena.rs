@@ -0,0 +1,181 @@
+// The playfield is always the same ARENA_WIDTH by ARENA_HEIGHT units, what
+// changes with the window is only how big it's drawn. The camera shows exactly
+// the arena in the largest viewport of the same shape that fits, and black
+// bars fill the rest of the window.
+
+use bevy::{
+    prelude::*,
+    render::{
+        camera::{ScalingMode, Viewport},
+        view::RenderLayers,
+    },
+    window::PrimaryWindow,
+};
+use game::{ARENA_HEIGHT, ARENA_WIDTH};
+
+use crate::cleanup;
+
+const BACKGROUND: Color = Color::srgb(0.17, 0.17, 0.18);
+const LINE_COLOR: Color = Color::srgb(0.45, 0.45, 0.45);
+const BORDER_WIDTH: f32 = 4.;
+const DASH_LENGTH: f32 = 24.;
+const DASHES: usize = 15;
+
+// the render layer nothing is on, for the camera that only draws the bars
+const BARS_LAYER: usize = 1;
+
+// the camera that draws the game, as opposed to the one drawing the bars
+#[derive(Component)]
+pub struct ArenaCamera;
+
+pub fn spawn_cameras(mut commands: Commands) {
+    commands.spawn((
+        ArenaCamera,
+        Camera2d,
+        Camera {
+            clear_color: ClearColorConfig::Custom(BACKGROUND),
+            ..default()
+        },
+        Projection::Orthographic(OrthographicProjection {
+            scaling_mode: ScalingMode::Fixed {
+                width: ARENA_WIDTH,
+                height: ARENA_HEIGHT,
+            },
+            ..OrthographicProjection::default_2d()
+        }),
+    ));
+    // clears the whole window, the arena camera then draws over its viewport
+    commands.spawn((
+        Camera2d,
+        Camera {
+            order: -1,
+            clear_color: ClearColorConfig::Custom(Color::BLACK),
+            ..default()
+        },
+        RenderLayers::layer(BARS_LAYER),
+    ));
+}
+
+pub fn fit_viewport(
+    windows: Query<&Window, With<PrimaryWindow>>,
+    mut cameras: Query<&mut Camera, With<ArenaCamera>>,
+) {
+    let Ok(window) = windows.single() else {
+        return;
+    };
+    let Some((physical_position, physical_size)) = letterbox(window.physical_size()) else {
+        return;
+    };
+    for mut camera in &mut cameras {
+        let changed = camera.viewport.as_ref().is_none_or(|current| {
+            (current.physical_position, current.physical_size) != (physical_position, physical_size)
+        });
+        if changed {
+            camera.viewport = Some(Viewport {
+                physical_position,
+                physical_size,
+                ..default()
+            });
+        }
+    }
+}
+
+// Position and size of the largest viewport of the arena's shape that fits a
+// window of `window_size` pixels, centered. Nothing fits a minimized window.
+fn letterbox(window_size: UVec2) -> Option<(UVec2, UVec2)> {
+    let window_size = window_size.as_vec2();
+    if window_size.min_element() < 1. {
+        return None;
+    }
+    let arena = Vec2::new(ARENA_WIDTH, ARENA_HEIGHT);
+    let scale = (window_size / arena).min_element();
+    let size = (arena * scale).round();
+    Some((
+        ((window_size - size) / 2.).as_uvec2(),
+        size.as_uvec2().max(UVec2::ONE),
+    ))
+}
+
+// walls along the top and bottom, goal lines at either end
+pub fn spawn_borders(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    let material = materials.add(LINE_COLOR);
+    let horizontal = meshes.add(Rectangle::new(ARENA_WIDTH, BORDER_WIDTH));
+    let vertical = meshes.add(Rectangle::new(BORDER_WIDTH, ARENA_HEIGHT));
+    let edge_x = (ARENA_WIDTH - BORDER_WIDTH) / 2.;
+    let edge_y = (ARENA_HEIGHT - BORDER_WIDTH) / 2.;
+    for (mesh, position) in [
+        (&horizontal, Vec2::new(0., edge_y)),
+        (&horizontal, Vec2::new(0., -edge_y)),
+        (&vertical, Vec2::new(edge_x, 0.)),
+        (&vertical, Vec2::new(-edge_x, 0.)),
+    ] {
+        commands.spawn((
+            Mesh2d(mesh.clone()),
+            MeshMaterial2d(material.clone()),
+            Transform::from_translation(position.extend(-1.)),
+        ));
+    }
+}
+
+// dashed, only while a match is on
+pub fn spawn_center_line(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    let material = materials.add(LINE_COLOR);
+    let dash = meshes.add(Rectangle::new(BORDER_WIDTH, DASH_LENGTH));
+    let spacing = ARENA_HEIGHT / DASHES as f32;
+    for i in 0..DASHES {
+        let y = -ARENA_HEIGHT / 2. + spacing * (i as f32 + 0.5);
+        commands.spawn((
+            Mesh2d(dash.clone()),
+            MeshMaterial2d(material.clone()),
+            Transform::from_translation(Vec3::new(0., y, -1.)),
+            cleanup::MenuToInGameCleanup,
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn windows_the_arenas_shape_are_filled() {
+        assert_eq!(
+            letterbox(UVec2::new(1280, 720)),
+            Some((UVec2::ZERO, UVec2::new(1280, 720)))
+        );
+        assert_eq!(
+            letterbox(UVec2::new(640, 360)),
+            Some((UVec2::ZERO, UVec2::new(640, 360)))
+        );
+    }
+
+    #[test]
+    fn wide_windows_get_bars_at_the_sides() {
+        assert_eq!(
+            letterbox(UVec2::new(2000, 720)),
+            Some((UVec2::new(360, 0), UVec2::new(1280, 720)))
+        );
+    }
+
+    #[test]
+    fn tall_windows_get_bars_above_and_below() {
+        assert_eq!(
+            letterbox(UVec2::new(640, 1000)),
+            Some((UVec2::new(0, 320), UVec2::new(640, 360)))
+        );
+    }
+
+    #[test]
+    fn minimized_windows_are_left_alone() {
+        assert_eq!(letterbox(UVec2::ZERO), None);
+        assert_eq!(letterbox(UVec2::new(1280, 0)), None);
+    }
+}
@@ -0,0 +1,81 @@
+// Everything the game needs, loaded once while the Loading state shows its
+// progress: the font from `assets/`, and the sounds, which are made in code
+// one a frame, see `audio`. Anything that fails to load is replaced by a
+// fallback so the game still runs, the default font for text.
+
+use bevy::{asset::LoadState, prelude::*};
+
+use crate::{
+    GameState,
+    audio::{Sound, Sounds},
+    cleanup,
+};
+
+const FONT: &str = "fonts/FiraSans-Bold.ttf";
+
+#[derive(Resource)]
+pub struct GameAssets {
+    pub font: Handle<Font>,
+    pub sounds: Sounds,
+}
+
+impl GameAssets {
+    pub fn text_font(&self, font_size: f32) -> TextFont {
+        TextFont {
+            font: self.font.clone(),
+            font_size,
+            ..default()
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct LoadingText;
+
+pub fn load(mut commands: Commands, asset_server: Res<AssetServer>, sounds: Res<Assets<Sound>>) {
+    commands.insert_resource(GameAssets {
+        font: asset_server.load(FONT),
+        sounds: Sounds::reserve(&sounds),
+    });
+    // in the default font, ours isn't there yet
+    commands.spawn((
+        LoadingText,
+        Text2d::default(),
+        TextLayout::new_with_justify(JustifyText::Center),
+        cleanup::LoadingCleanup,
+    ));
+}
+
+pub fn wait(
+    asset_server: Res<AssetServer>,
+    mut assets: ResMut<GameAssets>,
+    mut sounds: ResMut<Assets<Sound>>,
+    mut query: Query<&mut Text2d, With<LoadingText>>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    let font = settle(&asset_server, &mut assets.font, FONT);
+    let (sounds_ready, sounds) = assets.sounds.synthesize_next(&mut sounds);
+    let done = usize::from(font) + sounds_ready;
+    let total = 1 + sounds;
+    for mut loading_text in &mut query {
+        loading_text.0 = format!("Loading {} / {}", done, total);
+    }
+    if done == total {
+        next_state.set(GameState::Menu);
+    }
+}
+
+// whether `handle` is done loading, swapping it for the fallback if it failed
+fn settle<A: Asset>(asset_server: &AssetServer, handle: &mut Handle<A>, path: &str) -> bool {
+    match asset_server.get_load_state(handle.id()) {
+        Some(LoadState::Loaded) => true,
+        Some(LoadState::Failed(e)) => {
+            warn!("couldn't load {}, using a fallback: {}", path, e);
+            *handle = Handle::default();
+            true
+        }
+        // the fallback itself, or still loading
+        None => *handle == Handle::default(),
+        Some(_) => false,
+    }
+}
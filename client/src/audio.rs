@@ -0,0 +1,258 @@
+// Sound effects for what happens in a match, and music in the background.
+//
+// The sounds are synthesized while the game is loading rather than loaded from
+// files: a few short tones and a simple loop are all a game of pong needs, and
+// nothing has to be decoded, shipped to the browser or licensed.
+
+use std::{f32::consts::TAU, sync::Arc, time::Duration};
+
+use bevy::{
+    audio::{AddAudioSource, Decodable, Source, Volume},
+    prelude::*,
+};
+
+use crate::{
+    Match,
+    assets::GameAssets,
+    events::{MatchWon, PaddleHit, PointScored, WallHit},
+    settings::Settings,
+};
+
+const SAMPLE_RATE: u32 = 22050;
+
+// Note frequencies, from A3 up.
+const A3: f32 = 220.;
+const C4: f32 = 261.63;
+const D4: f32 = 293.66;
+const E4: f32 = 329.63;
+const F4: f32 = 349.23;
+const G4: f32 = 392.;
+const A4: f32 = 440.;
+const B4: f32 = 493.88;
+const C5: f32 = 523.25;
+const E5: f32 = 659.25;
+const G5: f32 = 783.99;
+const C6: f32 = 1046.5;
+
+/// Mono samples in `-1.0..=1.0` at [`SAMPLE_RATE`].
+#[derive(Asset, TypePath)]
+pub struct Sound {
+    samples: Arc<[f32]>,
+}
+
+impl Decodable for Sound {
+    type DecoderItem = f32;
+    type Decoder = SoundDecoder;
+
+    fn decoder(&self) -> Self::Decoder {
+        SoundDecoder {
+            samples: self.samples.clone(),
+            position: 0,
+        }
+    }
+}
+
+pub struct SoundDecoder {
+    samples: Arc<[f32]>,
+    position: usize,
+}
+
+impl Iterator for SoundDecoder {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let sample = self.samples.get(self.position).copied();
+        self.position += 1;
+        sample
+    }
+}
+
+impl Source for SoundDecoder {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        1
+    }
+
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        Some(Duration::from_secs_f32(
+            self.samples.len() as f32 / SAMPLE_RATE as f32,
+        ))
+    }
+}
+
+/// Registers [`Sound`] with Bevy's audio, which tests run without.
+pub fn add(app: &mut App) {
+    app.add_audio_source::<Sound>();
+}
+
+// makes a sound, which can take a moment for longer ones like the music
+type Recipe = fn() -> Sound;
+
+pub struct Sounds {
+    pub paddle_hit: Handle<Sound>,
+    pub wall_hit: Handle<Sound>,
+    pub point: Handle<Sound>,
+    pub game_over: Handle<Sound>,
+    pub music: Handle<Sound>,
+}
+
+impl Sounds {
+    /// Handles to every sound, which only play once `synthesize_next` has
+    /// made them.
+    pub fn reserve(sounds: &Assets<Sound>) -> Self {
+        Sounds {
+            paddle_hit: sounds.reserve_handle(),
+            wall_hit: sounds.reserve_handle(),
+            point: sounds.reserve_handle(),
+            game_over: sounds.reserve_handle(),
+            music: sounds.reserve_handle(),
+        }
+    }
+
+    fn recipes(&self) -> [(&Handle<Sound>, Recipe); 5] {
+        [
+            (&self.paddle_hit, || melody(&[(A4, 0.08)], 0.5)),
+            (&self.wall_hit, || melody(&[(E4, 0.06)], 0.4)),
+            (&self.point, || melody(&[(C5, 0.1), (G5, 0.2)], 0.5)),
+            (&self.game_over, || {
+                melody(&[(C5, 0.12), (E5, 0.12), (G5, 0.12), (C6, 0.4)], 0.5)
+            }),
+            (&self.music, music),
+        ]
+    }
+
+    /// Makes the first sound that isn't there yet, if any. Returns how many
+    /// sounds are ready, out of how many.
+    pub fn synthesize_next(&self, sounds: &mut Assets<Sound>) -> (usize, usize) {
+        let recipes = self.recipes();
+        let mut ready = recipes
+            .iter()
+            .filter(|(handle, _)| sounds.contains(*handle))
+            .count();
+        if let Some((handle, recipe)) = recipes.iter().find(|(handle, _)| !sounds.contains(*handle))
+        {
+            sounds.insert(*handle, recipe());
+            ready += 1;
+        }
+        (ready, recipes.len())
+    }
+}
+
+// notes played one after the other, each a frequency and a length in seconds
+fn melody(notes: &[(f32, f32)], volume: f32) -> Sound {
+    let mut samples = Vec::new();
+    for &(frequency, length) in notes {
+        samples.extend(note(frequency, length, volume));
+    }
+    Sound {
+        samples: samples.into(),
+    }
+}
+
+// A soft square-ish wave, the first two odd harmonics of one, that starts
+// without a click and dies away over its length.
+fn note(frequency: f32, length: f32, volume: f32) -> impl Iterator<Item = f32> {
+    let count = (length * SAMPLE_RATE as f32) as usize;
+    (0..count).map(move |i| {
+        let t = i as f32 / SAMPLE_RATE as f32;
+        let phase = TAU * frequency * t;
+        let wave = phase.sin() + (3. * phase).sin() / 3.;
+        let attack = (t / 0.005).min(1.);
+        let decay = (1. - i as f32 / count as f32).powi(2);
+        wave * attack * decay * volume * 0.75
+    })
+}
+
+// Four bars of arpeggiated chords, made to loop.
+fn music() -> Sound {
+    const STEP: f32 = 0.25;
+    let chords = [[A3, C4, E4], [F4, A4, C5], [C4, E4, G4], [G4, B4, D4]];
+    let mut samples = Vec::new();
+    for chord in chords {
+        for i in 0..8 {
+            // up and back down again
+            let pitch = chord[[0, 1, 2, 1][i % 4]];
+            samples.extend(note(pitch, STEP, 0.2));
+        }
+    }
+    Sound {
+        samples: samples.into(),
+    }
+}
+
+#[derive(Component)]
+pub struct Music;
+
+pub fn start_music(mut commands: Commands, assets: Res<GameAssets>, settings: Res<Settings>) {
+    commands.spawn((
+        Music,
+        AudioPlayer(assets.sounds.music.clone()),
+        PlaybackSettings::LOOP.with_volume(Volume::Linear(settings.music_volume())),
+    ));
+}
+
+pub fn apply_volume(settings: Res<Settings>, mut music: Query<&mut AudioSink, With<Music>>) {
+    for mut sink in &mut music {
+        sink.set_volume(Volume::Linear(settings.music_volume()));
+    }
+}
+
+// The ball sounds higher the faster it goes, an octave up at twice the speed
+// it was served at. Points and the end of the match always sound the same.
+#[allow(clippy::too_many_arguments)]
+pub fn play_effects(
+    mut commands: Commands,
+    mut paddle_hits: EventReader<PaddleHit>,
+    mut wall_hits: EventReader<WallHit>,
+    mut points: EventReader<PointScored>,
+    mut match_won: EventReader<MatchWon>,
+    assets: Res<GameAssets>,
+    match_: Option<Res<Match>>,
+    settings: Res<Settings>,
+) {
+    let volume = settings.effects_volume();
+    if volume == 0. {
+        // still read, so nothing plays late once unmuted
+        paddle_hits.clear();
+        wall_hits.clear();
+        points.clear();
+        match_won.clear();
+        return;
+    }
+    let sounds = &assets.sounds;
+    let pitch = match_.map_or(1., |match_| {
+        let sim = &match_.0;
+        (sim.ball.speed / sim.config.ball_speed).clamp(0.5, 2.)
+    });
+
+    let mut play = |sound: &Handle<Sound>, speed: f32| {
+        commands.spawn((
+            AudioPlayer(sound.clone()),
+            PlaybackSettings::DESPAWN
+                .with_volume(Volume::Linear(volume))
+                .with_speed(speed),
+        ));
+    };
+    // one sound for however many hits there were this frame
+    if paddle_hits.read().count() > 0 {
+        play(&sounds.paddle_hit, pitch);
+    }
+    if wall_hits.read().count() > 0 {
+        play(&sounds.wall_hit, pitch);
+    }
+    // the point that wins the match gets the game over sound instead
+    let won = match_won.read().count() > 0;
+    let scored = points.read().count() > 0;
+    if won {
+        play(&sounds.game_over, 1.);
+    } else if scored {
+        play(&sounds.point, 1.);
+    }
+}
@@ -0,0 +1,381 @@
+// What steers the paddles. Each of the two players has a binding, player 1
+// plays the left paddle and player 2 the right one when sharing a keyboard.
+// Playing alone, online or against the computer, either player's binding
+// steers, a pointer only while dragging so a cursor left in the window doesn't
+// pull against the keys.
+
+use bevy::{ecs::system::SystemParam, prelude::*, window::PrimaryWindow};
+use game::{PaddleSide, Simulation, TICK_DT};
+use serde::{Deserialize, Serialize};
+
+use crate::{GameState, arena, assets::GameAssets, cleanup, settings::Settings, storage};
+
+const BINDINGS_FILE: &str = "controls.ron";
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Binding {
+    Keys { up: KeyCode, down: KeyCode },
+    // the nth gamepad connected, its left stick or d-pad
+    Gamepad(usize),
+    // the paddle follows the mouse cursor or a finger on the screen
+    Pointer,
+}
+
+impl std::fmt::Display for Binding {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Binding::Keys { up, down } => write!(f, "{} / {}", key_name(*up), key_name(*down)),
+            Binding::Gamepad(n) => write!(f, "gamepad {}", n + 1),
+            Binding::Pointer => write!(f, "mouse or touch"),
+        }
+    }
+}
+
+// `KeyW` as `W`, `ArrowUp` as `Up`
+fn key_name(key: KeyCode) -> String {
+    let name = format!("{:?}", key);
+    ["Key", "Digit", "Arrow"]
+        .into_iter()
+        .find_map(|prefix| name.strip_prefix(prefix).filter(|rest| !rest.is_empty()))
+        .unwrap_or(&name)
+        .to_string()
+}
+
+#[derive(Resource, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Bindings {
+    pub players: [Binding; 2],
+}
+
+impl Default for Bindings {
+    fn default() -> Self {
+        Bindings {
+            players: [
+                Binding::Keys {
+                    up: KeyCode::KeyW,
+                    down: KeyCode::KeyS,
+                },
+                Binding::Keys {
+                    up: KeyCode::ArrowUp,
+                    down: KeyCode::ArrowDown,
+                },
+            ],
+        }
+    }
+}
+
+impl Bindings {
+    // falls back to the defaults if nothing was saved or it doesn't parse
+    pub fn load() -> Self {
+        storage::read(BINDINGS_FILE)
+            .and_then(|saved| ron::from_str(&saved).ok())
+            .unwrap_or_default()
+    }
+
+    fn save(&self) {
+        match ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default()) {
+            Ok(contents) => storage::write(BINDINGS_FILE, &contents),
+            Err(e) => error!("couldn't save the controls: {}", e),
+        }
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct Pointer {
+    // height in the arena the mouse or a finger is at, if any
+    y: Option<f32>,
+    // a finger is down or a mouse button held
+    dragging: bool,
+}
+
+pub fn track_pointer(
+    windows: Query<&Window, With<PrimaryWindow>>,
+    cameras: Query<(&Camera, &GlobalTransform), With<arena::ArenaCamera>>,
+    touches: Res<Touches>,
+    buttons: Res<ButtonInput<MouseButton>>,
+    mut pointer: ResMut<Pointer>,
+) {
+    let (Ok(window), Ok((camera, transform))) = (windows.single(), cameras.single()) else {
+        return;
+    };
+    let on_screen = touches
+        .iter()
+        .next()
+        .map(|touch| touch.position())
+        .or_else(|| window.cursor_position());
+    pointer.y = on_screen
+        .and_then(|position| camera.viewport_to_world_2d(transform, position).ok())
+        .map(|position| position.y);
+    pointer.dragging = touches.iter().next().is_some() || buttons.get_pressed().next().is_some();
+}
+
+// gamepads in the order they were connected
+fn gamepads<'a>(query: &'a Query<(Entity, &Gamepad)>) -> Vec<&'a Gamepad> {
+    let mut gamepads: Vec<_> = query.iter().collect();
+    gamepads.sort_by_key(|(entity, _)| *entity);
+    gamepads.into_iter().map(|(_, gamepad)| gamepad).collect()
+}
+
+/// Reads the movement the players ask for through their bindings.
+#[derive(SystemParam)]
+pub struct Controls<'w, 's> {
+    bindings: Res<'w, Bindings>,
+    keys: Res<'w, ButtonInput<KeyCode>>,
+    gamepads: Query<'w, 's, (Entity, &'static Gamepad)>,
+    pointer: Res<'w, Pointer>,
+}
+
+impl Controls<'_, '_> {
+    /// Movement in `-1.0..=1.0` for player `player`, who steers `side`'s
+    /// paddle in `sim`. Sticks and pointers give anything in between.
+    pub fn player(&self, player: usize, sim: &Simulation, side: PaddleSide) -> f32 {
+        self.binding(self.bindings.players[player], sim, side)
+    }
+
+    /// Movement for the paddle of someone playing alone.
+    pub fn anyone(&self, sim: &Simulation, side: PaddleSide) -> f32 {
+        let direction: f32 = self
+            .bindings
+            .players
+            .iter()
+            .map(|&binding| match binding {
+                Binding::Pointer if !self.pointer.dragging => 0.,
+                binding => self.binding(binding, sim, side),
+            })
+            .sum();
+        direction.clamp(-1., 1.)
+    }
+
+    fn binding(&self, binding: Binding, sim: &Simulation, side: PaddleSide) -> f32 {
+        let direction = match binding {
+            Binding::Keys { up, down } => {
+                f32::from(self.keys.pressed(up)) - f32::from(self.keys.pressed(down))
+            }
+            Binding::Gamepad(n) => gamepads(&self.gamepads)
+                .get(n)
+                .map_or(0., |gamepad| gamepad.left_stick().y + gamepad.dpad().y),
+            // as fast as it takes to catch up with the pointer this tick
+            Binding::Pointer => self.pointer.y.map_or(0., |y| {
+                let step = sim.config.paddle_move_speed * TICK_DT;
+                // a paddle that can't move has nowhere to go
+                if step > 0. {
+                    (y - sim.paddle(side).position.y) / step
+                } else {
+                    0.
+                }
+            }),
+        };
+        direction.clamp(-1., 1.)
+    }
+}
+
+// the player whose binding is being changed, and the up key if they already
+// pressed it
+#[derive(Resource)]
+pub struct Rebinding {
+    player: usize,
+    up: Option<KeyCode>,
+}
+
+#[derive(Component)]
+pub struct ControlsText;
+
+pub fn spawn(mut commands: Commands, assets: Res<GameAssets>, settings: Res<Settings>) {
+    commands.spawn((
+        ControlsText,
+        Text2d::default(),
+        assets.text_font(settings.font_size),
+        TextLayout::new_with_justify(JustifyText::Center),
+        cleanup::ControlsCleanup,
+    ));
+}
+
+pub fn update_text(
+    bindings: Res<Bindings>,
+    rebinding: Option<Res<Rebinding>>,
+    mut query: Query<&mut Text2d, With<ControlsText>>,
+) {
+    let mut text = String::new();
+    for (player, binding) in bindings.players.iter().enumerate() {
+        text += &format!("Player {}: {}\n", player + 1, binding);
+    }
+    text += "\n";
+    text += match rebinding.as_deref() {
+        None => "Press 1 or 2 to change a player's controls\nEscape to go back",
+        Some(Rebinding { up: None, .. }) => {
+            "Press the up key, a gamepad button\nor click to use the mouse"
+        }
+        Some(Rebinding { up: Some(_), .. }) => "Press the down key",
+    };
+    for mut controls_text in &mut query {
+        if controls_text.0 != text {
+            controls_text.0 = text.clone();
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn rebind(
+    mut commands: Commands,
+    keys: Res<ButtonInput<KeyCode>>,
+    buttons: Res<ButtonInput<MouseButton>>,
+    touches: Res<Touches>,
+    gamepad_query: Query<(Entity, &Gamepad)>,
+    mut bindings: ResMut<Bindings>,
+    rebinding: Option<ResMut<Rebinding>>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    let Some(mut rebinding) = rebinding else {
+        if keys.just_pressed(KeyCode::Escape) {
+            next_state.set(GameState::Settings);
+        }
+        for (player, key) in [KeyCode::Digit1, KeyCode::Digit2].into_iter().enumerate() {
+            if keys.just_pressed(key) {
+                commands.insert_resource(Rebinding { player, up: None });
+            }
+        }
+        return;
+    };
+
+    if keys.just_pressed(KeyCode::Escape) {
+        commands.remove_resource::<Rebinding>();
+        return;
+    }
+
+    let binding = if let Some(&key) = keys.get_just_pressed().next() {
+        match rebinding.up {
+            None => {
+                rebinding.up = Some(key);
+                return;
+            }
+            Some(up) if up != key => Binding::Keys { up, down: key },
+            Some(_) => return,
+        }
+    } else if rebinding.up.is_some() {
+        return;
+    } else if let Some(n) = gamepads(&gamepad_query).iter().position(|gamepad| {
+        gamepad.get_just_pressed().next().is_some() || gamepad.left_stick().y.abs() > 0.5
+    }) {
+        Binding::Gamepad(n)
+    } else if buttons.get_just_pressed().next().is_some() || touches.any_just_pressed() {
+        Binding::Pointer
+    } else {
+        return;
+    };
+
+    bindings.players[rebinding.player] = binding;
+    bindings.save();
+    commands.remove_resource::<Rebinding>();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::ecs::system::RunSystemOnce;
+
+    fn world(bindings: [Binding; 2]) -> World {
+        let mut world = World::new();
+        world.insert_resource(Bindings { players: bindings });
+        world.init_resource::<ButtonInput<KeyCode>>();
+        world.init_resource::<ButtonInput<MouseButton>>();
+        world.init_resource::<Touches>();
+        world.init_resource::<Pointer>();
+        world.init_resource::<NextState<GameState>>();
+        world
+    }
+
+    const WS: Binding = Binding::Keys {
+        up: KeyCode::KeyW,
+        down: KeyCode::KeyS,
+    };
+
+    fn press(world: &mut World, key: KeyCode) {
+        world.resource_mut::<ButtonInput<KeyCode>>().press(key);
+    }
+
+    // what `player` and `anyone` ask for the left paddle of `sim`
+    fn steer(world: &mut World, sim: Simulation) -> ([f32; 2], f32) {
+        world
+            .run_system_once(move |controls: Controls| {
+                let side = PaddleSide::Left;
+                let players = [0, 1].map(|player| controls.player(player, &sim, side));
+                (players, controls.anyone(&sim, side))
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn keys_steer_their_own_player() {
+        let mut world = world([WS, Binding::Gamepad(0)]);
+        press(&mut world, KeyCode::KeyW);
+        assert_eq!(steer(&mut world, Simulation::new(0)), ([1., 0.], 1.));
+        press(&mut world, KeyCode::KeyS);
+        assert_eq!(steer(&mut world, Simulation::new(0)), ([0., 0.], 0.));
+    }
+
+    #[test]
+    fn pointers_catch_up_as_fast_as_the_paddle_can_go() {
+        let mut world = world([WS, Binding::Pointer]);
+        let step = game::PADDLE_MOVE_SPEED * TICK_DT;
+        world.resource_mut::<Pointer>().y = Some(step / 2.);
+        assert_eq!(steer(&mut world, Simulation::new(0)).0, [0., 0.5]);
+        world.resource_mut::<Pointer>().y = Some(-300.);
+        assert_eq!(steer(&mut world, Simulation::new(0)).0, [0., -1.]);
+
+        let mut stuck = Simulation::new(0);
+        stuck.config.paddle_move_speed = 0.;
+        assert_eq!(steer(&mut world, stuck).0, [0., 0.]);
+    }
+
+    #[test]
+    fn playing_alone_a_pointer_only_steers_while_dragging() {
+        let mut world = world([WS, Binding::Pointer]);
+        world.resource_mut::<Pointer>().y = Some(300.);
+        assert_eq!(steer(&mut world, Simulation::new(0)).1, 0.);
+        press(&mut world, KeyCode::KeyS);
+        assert_eq!(steer(&mut world, Simulation::new(0)).1, -1.);
+
+        world.resource_mut::<ButtonInput<KeyCode>>().reset_all();
+        world.resource_mut::<Pointer>().dragging = true;
+        assert_eq!(steer(&mut world, Simulation::new(0)).1, 1.);
+    }
+
+    #[test]
+    fn rebinding_takes_the_up_then_the_down_key() {
+        let mut world = world([WS, WS]);
+        world.insert_resource(Rebinding {
+            player: 1,
+            up: None,
+        });
+        for key in [KeyCode::KeyI, KeyCode::KeyK] {
+            let mut keys = world.resource_mut::<ButtonInput<KeyCode>>();
+            keys.clear();
+            keys.press(key);
+            world.run_system_once(rebind).unwrap();
+        }
+        assert_eq!(
+            world.resource::<Bindings>().players,
+            [
+                WS,
+                Binding::Keys {
+                    up: KeyCode::KeyI,
+                    down: KeyCode::KeyK
+                }
+            ]
+        );
+        assert!(!world.contains_resource::<Rebinding>());
+    }
+
+    #[test]
+    fn rebinding_to_the_pointer_takes_a_click() {
+        let mut world = world([WS, WS]);
+        world.insert_resource(Rebinding {
+            player: 0,
+            up: None,
+        });
+        world
+            .resource_mut::<ButtonInput<MouseButton>>()
+            .press(MouseButton::Left);
+        world.run_system_once(rebind).unwrap();
+        assert_eq!(world.resource::<Bindings>().players, [Binding::Pointer, WS]);
+    }
+}
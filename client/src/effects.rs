@@ -0,0 +1,205 @@
+// Purely cosmetic effects on top of the match: a trail behind the ball,
+// particles and a flash when a paddle hits it, and the screen shaking when a
+// point is scored. They can all be turned off from the settings, for anyone
+// who'd rather nothing moved on screen but the ball and paddles.
+//
+// Nothing here needs random numbers: particles spread out evenly and the
+// shake is worked out from the time.
+
+use std::f32::consts::PI;
+
+use bevy::prelude::*;
+use game::BALL_RADIUS;
+
+use crate::{
+    arena::ArenaCamera,
+    events::{PaddleHit, PointScored},
+    ingame::{Ball, Paddle},
+    settings::Settings,
+};
+
+const TRAIL_LIFETIME: f32 = 0.15;
+const TRAIL_COLOR: Color = Color::srgba(1., 1., 1., 0.25);
+
+const PARTICLES: usize = 12;
+const PARTICLE_SIZE: f32 = 6.;
+const PARTICLE_SPEED: f32 = 500.;
+const PARTICLE_LIFETIME: f32 = 0.35;
+// how far either side of straight back out the particles fly, in radians
+const PARTICLE_SPREAD: f32 = PI / 3.;
+
+const FLASH_COLOR: Color = Color::srgb(1., 0.85, 0.3);
+const FLASH_LIFETIME: f32 = 0.2;
+
+const SHAKE_STRENGTH: f32 = 12.;
+const SHAKE_LIFETIME: f32 = 0.3;
+
+/// Whether the settings have the effects on.
+pub fn enabled(settings: Res<Settings>) -> bool {
+    settings.visual_effects
+}
+
+// shared by every piece of trail and particle, so spawning them is cheap
+#[derive(Resource)]
+pub struct EffectAssets {
+    trail_mesh: Handle<Mesh>,
+    trail_material: Handle<ColorMaterial>,
+    particle_mesh: Handle<Mesh>,
+    particle_material: Handle<ColorMaterial>,
+}
+
+pub fn setup(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    commands.insert_resource(EffectAssets {
+        trail_mesh: meshes.add(Circle::new(BALL_RADIUS)),
+        trail_material: materials.add(TRAIL_COLOR),
+        particle_mesh: meshes.add(Rectangle::new(PARTICLE_SIZE, PARTICLE_SIZE)),
+        particle_material: materials.add(FLASH_COLOR),
+    });
+    commands.init_resource::<Shake>();
+}
+
+// Trail and hit particles alike, shrinking away to nothing over their
+// lifetime. Trail just doesn't move.
+#[derive(Component)]
+pub struct Particle {
+    velocity: Vec2,
+    timer: Timer,
+}
+
+impl Particle {
+    fn new(velocity: Vec2, lifetime: f32) -> Self {
+        Particle {
+            velocity,
+            timer: Timer::from_seconds(lifetime, TimerMode::Once),
+        }
+    }
+}
+
+// a piece of trail where the ball is drawn this frame
+pub fn spawn_trail(
+    mut commands: Commands,
+    assets: Res<EffectAssets>,
+    ball_query: Query<&Transform, With<Ball>>,
+) {
+    for transform in &ball_query {
+        commands.spawn((
+            Particle::new(Vec2::ZERO, TRAIL_LIFETIME),
+            Mesh2d(assets.trail_mesh.clone()),
+            MeshMaterial2d(assets.trail_material.clone()),
+            // behind the ball, in front of the lines
+            Transform::from_translation(transform.translation.truncate().extend(-0.5)),
+        ));
+    }
+}
+
+// particles bursting back out from the paddle, and the paddle flashing
+pub fn paddle_hit(
+    mut commands: Commands,
+    mut hits: EventReader<PaddleHit>,
+    assets: Res<EffectAssets>,
+    ball_query: Query<&Transform, With<Ball>>,
+    paddles_query: Query<(Entity, &Paddle)>,
+) {
+    for hit in hits.read() {
+        if let Ok(ball) = ball_query.single() {
+            let out = hit.side.facing().to_angle();
+            for i in 0..PARTICLES {
+                // evenly across the spread, every other one a bit slower
+                let fraction = i as f32 / (PARTICLES - 1) as f32;
+                let angle = out + PARTICLE_SPREAD * (2. * fraction - 1.);
+                let speed = PARTICLE_SPEED * if i % 2 == 0 { 1. } else { 0.6 };
+                commands.spawn((
+                    Particle::new(Vec2::from_angle(angle) * speed, PARTICLE_LIFETIME),
+                    Mesh2d(assets.particle_mesh.clone()),
+                    MeshMaterial2d(assets.particle_material.clone()),
+                    Transform::from_translation(ball.translation.truncate().extend(0.5)),
+                ));
+            }
+        }
+        for (entity, paddle) in &paddles_query {
+            if paddle.0 == hit.side {
+                commands
+                    .entity(entity)
+                    .insert(Flash(Timer::from_seconds(FLASH_LIFETIME, TimerMode::Once)));
+            }
+        }
+    }
+}
+
+pub fn update_particles(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut query: Query<(Entity, &mut Particle, &mut Transform)>,
+) {
+    for (entity, mut particle, mut transform) in &mut query {
+        particle.timer.tick(time.delta());
+        if particle.timer.finished() {
+            commands.entity(entity).despawn();
+            continue;
+        }
+        transform.translation += (particle.velocity * time.delta_secs()).extend(0.);
+        transform.scale = Vec3::splat(particle.timer.fraction_remaining());
+    }
+}
+
+// a paddle that was just hit, fading from the flash back to white
+#[derive(Component)]
+pub struct Flash(Timer);
+
+pub fn update_flash(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut query: Query<(Entity, &mut Flash, &MeshMaterial2d<ColorMaterial>)>,
+) {
+    for (entity, mut flash, material) in &mut query {
+        flash.0.tick(time.delta());
+        let Some(material) = materials.get_mut(&material.0) else {
+            continue;
+        };
+        material.color = Color::WHITE.mix(&FLASH_COLOR, flash.0.fraction_remaining());
+        if flash.0.finished() {
+            commands.entity(entity).remove::<Flash>();
+        }
+    }
+}
+
+// how much longer the camera shakes for
+#[derive(Resource, Default)]
+pub struct Shake(pub Option<Timer>);
+
+pub fn start_shake(mut points: EventReader<PointScored>, mut shake: ResMut<Shake>) {
+    if points.read().count() > 0 {
+        shake.0 = Some(Timer::from_seconds(SHAKE_LIFETIME, TimerMode::Once));
+    }
+}
+
+// Moves the camera rather than anything in the arena, dying down to exactly
+// where it was.
+pub fn update_shake(
+    time: Res<Time>,
+    mut shake: ResMut<Shake>,
+    mut cameras: Query<&mut Transform, With<ArenaCamera>>,
+) {
+    let Some(timer) = &mut shake.0 else {
+        return;
+    };
+    timer.tick(time.delta());
+    let offset = if timer.finished() {
+        shake.0 = None;
+        Vec2::ZERO
+    } else {
+        // two unrelated wobbles, so it doesn't just go back and forth
+        let t = time.elapsed_secs();
+        let wobble = Vec2::new((t * 71.).sin(), (t * 97. + 1.).sin());
+        wobble * SHAKE_STRENGTH * timer.fraction_remaining()
+    };
+    for mut transform in &mut cameras {
+        transform.translation.x = offset.x;
+        transform.translation.y = offset.y;
+    }
+}
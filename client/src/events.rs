@@ -0,0 +1,87 @@
+// What happens during a match, whether our own simulation stepped into it or
+// the server told us about it. The score text, stats and anything else that
+// reacts to the match listen for these rather than watching `Match` change.
+
+use bevy::{ecs::system::SystemParam, prelude::*};
+use game::PaddleSide;
+
+// `Match` was replaced by a new match, so anything kept about the last one
+// starts over
+#[derive(Event, Clone, Copy, Debug)]
+pub struct MatchStarted;
+
+// the replay being watched jumped to another tick, skipping whatever happened
+// on the way there
+#[derive(Event, Clone, Copy, Debug)]
+pub struct Seeked;
+
+#[derive(Event, Clone, Copy, Debug)]
+pub struct PointScored {
+    pub side: PaddleSide,
+}
+
+#[derive(Event, Clone, Copy, Debug)]
+pub struct PaddleHit {
+    pub side: PaddleSide,
+}
+
+#[derive(Event, Clone, Copy, Debug)]
+pub struct WallHit;
+
+// which side won is in the games of `Match`
+#[derive(Event, Clone, Copy, Debug)]
+pub struct GameWon;
+
+#[derive(Event, Clone, Copy, Debug)]
+pub struct MatchWon {
+    pub side: PaddleSide,
+}
+
+pub fn add(app: &mut App) {
+    app.add_event::<MatchStarted>()
+        .add_event::<Seeked>()
+        .add_event::<PointScored>()
+        .add_event::<PaddleHit>()
+        .add_event::<WallHit>()
+        .add_event::<GameWon>()
+        .add_event::<MatchWon>();
+}
+
+/// Sends the events of the simulation as Bevy events.
+#[derive(SystemParam)]
+pub struct GameplayEvents<'w> {
+    point_scored: EventWriter<'w, PointScored>,
+    paddle_hit: EventWriter<'w, PaddleHit>,
+    wall_hit: EventWriter<'w, WallHit>,
+    game_won: EventWriter<'w, GameWon>,
+    match_won: EventWriter<'w, MatchWon>,
+}
+
+impl GameplayEvents<'_> {
+    pub fn send(&mut self, event: game::Event) {
+        match event {
+            game::Event::PointScored(side) => {
+                self.point_scored.write(PointScored { side });
+            }
+            game::Event::PaddleHit(side) => {
+                self.paddle_hit.write(PaddleHit { side });
+            }
+            game::Event::WallHit => {
+                self.wall_hit.write(WallHit);
+            }
+            game::Event::GameWon(_) => {
+                self.game_won.write(GameWon);
+            }
+            game::Event::MatchWon(side) => {
+                self.match_won.write(MatchWon { side });
+            }
+        }
+    }
+
+    /// Everything that happened during the last step of `sim`.
+    pub fn send_all(&mut self, sim: &game::Simulation) {
+        for &event in &sim.events {
+            self.send(event);
+        }
+    }
+}
@@ -1,32 +1,43 @@
 use std::time::Duration;
 
-use bevy::{color::palettes::css::WHITE, prelude::*};
+use bevy::{asset::AssetMetaCheck, color::palettes::css::WHITE, prelude::*};
+use game::{ARENA_HEIGHT, ARENA_WIDTH, PaddleSide, Simulation};
+
+mod arena;
+mod assets;
+mod audio;
+mod controls;
+mod effects;
+mod events;
+mod net;
+mod replay;
+mod settings;
+mod stats;
+mod storage;
 
 // General critique of my code:
 // - should've used bounding boxes for 2D collisions
-// - should've used events for communication between game loop and score text
-
-const PADDLE_MOVE_SPEED: f32 = 15.;
-const PADDLE_WIDTH: f32 = 50.;
-const PADDLE_HEIGHT: f32 = 150.;
-const BALL_RADIUS: f32 = 15.;
-const BALL_SPEED: f32 = 30.;
-const FONT_SIZE: f32 = 50.;
-const POINTS_TO_WIN: usize = 3;
 
 #[derive(States, Default, Debug, Clone, PartialEq, Eq, Hash)]
 enum GameState {
     #[default]
+    Loading,
     Menu,
+    Settings,
+    Controls,
     InGame,
+    Paused,
     PointScored,
     GameOver,
+    Replay,
 }
 
 // from the unofficial bevy cheat book
 mod cleanup {
     use bevy::prelude::*;
     #[derive(Component)]
+    pub struct LoadingCleanup;
+    #[derive(Component)]
     pub struct MenuCleanup;
     #[derive(Component)]
     pub struct InGameCleanup;
@@ -34,10 +45,33 @@ mod cleanup {
     pub struct GameOverCleanup;
     #[derive(Component)]
     pub struct MenuToInGameCleanup;
+    #[derive(Component)]
+    pub struct ControlsCleanup;
+    #[derive(Component)]
+    pub struct SettingsCleanup;
+    #[derive(Component)]
+    pub struct PausedCleanup;
+    #[derive(Component)]
+    pub struct ReplayCleanup;
 }
 
+// the state of the current match, stepped by the ingame systems when playing
+// locally and overwritten by server snapshots when playing online
+#[derive(Resource)]
+pub struct Match(Simulation);
+
+#[derive(Resource)]
+pub struct Winner(PaddleSide);
+
 #[derive(Resource)]
-pub struct Score((usize, usize));
+pub enum Mode {
+    Local,
+    Online(String),
+}
+
+fn online(mode: Res<Mode>) -> bool {
+    matches!(*mode, Mode::Online(_))
+}
 
 fn cleanup_system<T: Component>(mut commands: Commands, q: Query<Entity, With<T>>) {
     for entity in q {
@@ -47,18 +81,17 @@ fn cleanup_system<T: Component>(mut commands: Commands, q: Query<Entity, With<T>
 
 mod menu {
     use crate::*;
+    use bevy::input::keyboard::{Key, KeyboardInput};
+    use game::ai::{Ai, Difficulty};
+    use protocol::RoomCode;
+
     pub fn spawn(
         mut commands: Commands,
-        asset_server: Res<AssetServer>,
-        window_query: Query<&Window>,
+        assets: Res<assets::GameAssets>,
+        mode: Res<Mode>,
+        settings: Res<settings::Settings>,
     ) {
-        let window = window_query.single().unwrap();
-        let font = asset_server.load("fonts/FiraSans-Bold.ttf");
-        let text_font = TextFont {
-            font: font.clone(),
-            font_size: FONT_SIZE,
-            ..default()
-        };
+        let text_font = assets.text_font(settings.font_size);
 
         commands.spawn((
             Text2d::new("Pong"),
@@ -67,175 +100,294 @@ mod menu {
             Transform::from_translation(Vec3::new(0., 0., 0.)),
             cleanup::MenuCleanup,
         ));
+        commands.spawn((
+            Text2d::new("Tab: settings"),
+            text_font.clone(),
+            TextLayout::new_with_justify(JustifyText::Center),
+            Transform::from_translation(Vec3::new(0., ARENA_HEIGHT / 2.5, 0.)),
+            cleanup::MenuCleanup,
+        ));
+
+        if !online(mode) {
+            commands.spawn((
+                Text2d::new(
+                    "Click for two players\n\
+                     or press 1, 2 or 3 to play the computer\n\
+                     (easy, medium or hard)\n\
+                     R: watch the last match",
+                ),
+                text_font.clone(),
+                TextLayout::new_with_justify(JustifyText::Center),
+                Transform::from_translation(Vec3::new(0., -ARENA_HEIGHT / 4., 0.)),
+                cleanup::MenuCleanup,
+            ));
+            return;
+        }
 
         commands.spawn((
-            Text2d::new("Press anywhere to start"),
+            Text2d::new(
+                "Click for a quick match\n\
+                 Press Enter to create a room\n\
+                 or type a room code and press Enter to join",
+            ),
             text_font.clone(),
             TextLayout::new_with_justify(JustifyText::Center),
-            Transform::from_translation(Vec3::new(0., -window.height() / 4., 0.)),
+            Transform::from_translation(Vec3::new(0., -ARENA_HEIGHT / 5., 0.)),
             cleanup::MenuCleanup,
         ));
+        commands.spawn((
+            RoomCodeInput(String::new()),
+            Text2d::new(room_code_text("")),
+            text_font.clone(),
+            TextLayout::new_with_justify(JustifyText::Center),
+            Transform::from_translation(Vec3::new(0., -ARENA_HEIGHT / 2.5, 0.)),
+            cleanup::MenuCleanup,
+        ));
+    }
+
+    // what has been typed of a room code so far
+    #[derive(Component)]
+    pub struct RoomCodeInput(String);
+
+    fn room_code_text(typed: &str) -> String {
+        format!("{:_<width$}", typed, width = RoomCode::LEN)
+    }
+
+    // clicking still asks for a quick match, see `handle_input`
+    pub fn handle_lobby_input(
+        mut commands: Commands,
+        mut keyboard: EventReader<KeyboardInput>,
+        mut query: Query<(&mut RoomCodeInput, &mut Text2d)>,
+        mut next_state: ResMut<NextState<GameState>>,
+    ) {
+        let (mut input, mut text) = query.single_mut().unwrap();
+        for event in keyboard.read() {
+            if !event.state.is_pressed() {
+                continue;
+            }
+            match &event.logical_key {
+                Key::Character(typed) => {
+                    for letter in typed.to_ascii_uppercase().chars() {
+                        if input.0.len() < RoomCode::LEN
+                            && RoomCode::ALPHABET.contains(&(letter as u8))
+                        {
+                            input.0.push(letter);
+                        }
+                    }
+                }
+                Key::Backspace => {
+                    input.0.pop();
+                }
+                Key::Enter if input.0.is_empty() => {
+                    commands.insert_resource(net::RoomRequest::Create);
+                    next_state.set(GameState::InGame);
+                }
+                Key::Enter => {
+                    if let Some(code) = RoomCode::parse(&input.0) {
+                        commands.insert_resource(net::RoomRequest::Join(code));
+                        next_state.set(GameState::InGame);
+                    }
+                }
+                _ => {}
+            }
+        }
+        if input.is_changed() {
+            *text = Text2d::new(room_code_text(&input.0));
+        }
     }
 
     pub fn handle_input(
+        mut commands: Commands,
         buttons: Res<ButtonInput<MouseButton>>,
+        keys: Res<ButtonInput<KeyCode>>,
         mut next_state: ResMut<NextState<GameState>>,
     ) {
         if buttons.just_pressed(MouseButton::Left) || buttons.just_pressed(MouseButton::Right) {
+            commands.remove_resource::<ingame::Computer>();
             next_state.set(GameState::InGame);
+        } else if keys.just_pressed(KeyCode::Tab) {
+            next_state.set(GameState::Settings);
+        }
+    }
+
+    // the computer always takes the right paddle, the player can use either
+    // set of keys
+    pub fn handle_computer_input(
+        mut commands: Commands,
+        keys: Res<ButtonInput<KeyCode>>,
+        time: Res<Time<Real>>,
+        mut next_state: ResMut<NextState<GameState>>,
+    ) {
+        let keys_and_difficulties = [KeyCode::Digit1, KeyCode::Digit2, KeyCode::Digit3]
+            .into_iter()
+            .zip(Difficulty::ALL);
+        for (key, difficulty) in keys_and_difficulties {
+            if keys.just_pressed(key) {
+                let seed = time.elapsed().as_nanos() as u64;
+                commands.insert_resource(ingame::Computer(Ai::new(
+                    PaddleSide::Right,
+                    difficulty,
+                    seed,
+                )));
+                next_state.set(GameState::InGame);
+            }
         }
     }
 }
 
 mod ingame {
     use crate::*;
+    use game::{BALL_RADIUS, PADDLE_HEIGHT, PADDLE_WIDTH, ai::Ai};
 
-    #[derive(Clone, Copy, PartialEq, Eq)]
-    enum PaddleSide {
-        Left,
-        Right,
-    }
+    #[derive(Component)]
+    pub struct Paddle(pub PaddleSide);
 
     #[derive(Component)]
-    pub struct Paddle(PaddleSide);
+    pub struct Ball;
 
     #[derive(Component)]
-    pub struct BallDirection(Vec3);
+    pub struct ServeCountdown;
+
+    #[derive(Resource, Default)]
+    pub struct PlayerInput(game::Input);
+
+    // the AI playing one of the paddles, when there is only one player
+    #[derive(Resource)]
+    pub struct Computer(pub Ai);
+
+    // state of the simulation before the last tick, so rendering can
+    // interpolate between ticks instead of stuttering at the tick rate
+    #[derive(Resource)]
+    pub struct PreviousTick(pub Simulation);
 
     pub fn spawn(
         mut commands: Commands,
+        assets: Res<assets::GameAssets>,
         mut meshes: ResMut<Assets<Mesh>>,
         mut materials: ResMut<Assets<ColorMaterial>>,
+        match_: Res<Match>,
+        settings: Res<settings::Settings>,
     ) {
-        [PaddleSide::Left, PaddleSide::Right]
-            .into_iter()
-            .for_each(|side| {
-                commands.spawn((
-                    Paddle(side),
-                    Mesh2d(meshes.add(Rectangle::new(PADDLE_WIDTH, PADDLE_HEIGHT))),
-                    MeshMaterial2d(materials.add(Color::from(WHITE))),
-                    Transform::from_translation(Vec3::new(
-                        (if side == PaddleSide::Left { -1. } else { 1. }) * 600.,
-                        0.,
-                        0.,
-                    )),
-                    cleanup::InGameCleanup,
-                ));
-            });
+        commands.insert_resource(PreviousTick(match_.0.clone()));
 
         commands.spawn((
-            BallDirection(Vec3::new(-1., 1., 0.).normalize()),
+            ServeCountdown,
+            Text2d::default(),
+            assets.text_font(settings.font_size),
+            TextLayout::new_with_justify(JustifyText::Center),
+            Transform::from_translation(Vec3::new(0., ARENA_HEIGHT / 6., 1.)),
+            cleanup::InGameCleanup,
+        ));
+
+        for paddle in match_.0.paddles {
+            commands.spawn((
+                Paddle(paddle.side),
+                Mesh2d(meshes.add(Rectangle::new(PADDLE_WIDTH, PADDLE_HEIGHT))),
+                MeshMaterial2d(materials.add(Color::from(WHITE))),
+                Transform::from_translation(paddle.position.extend(0.)),
+                cleanup::InGameCleanup,
+            ));
+        }
+
+        commands.spawn((
+            Ball,
             Mesh2d(meshes.add(Circle::new(BALL_RADIUS))),
             MeshMaterial2d(materials.add(Color::from(WHITE))),
-            Transform::from_translation(Vec3::new(0., 0., 0.)),
+            Transform::from_translation(match_.0.ball.position.extend(0.)),
             cleanup::InGameCleanup,
         ));
     }
 
     pub fn handle_input(
-        keys: Res<ButtonInput<KeyCode>>,
-        mut query: Query<(&Paddle, &mut Transform)>,
-        window_query: Query<&Window>,
+        controls: controls::Controls,
+        mut input: ResMut<PlayerInput>,
+        computer: Option<ResMut<Computer>>,
+        match_: Res<Match>,
     ) {
-        let window = window_query.single().unwrap();
-        for (side, mut transform) in &mut query {
-            let mut direction = Vec3::ZERO;
-
-            match side.0 {
-                PaddleSide::Left => {
-                    if keys.pressed(KeyCode::KeyW) {
-                        direction.y += 1.0;
-                    }
-                    if keys.pressed(KeyCode::KeyS) {
-                        direction.y -= 1.0;
-                    }
-                }
-                PaddleSide::Right => {
-                    if keys.pressed(KeyCode::ArrowUp) {
-                        direction.y += 1.0;
-                    }
-                    if keys.pressed(KeyCode::ArrowDown) {
-                        direction.y -= 1.0;
-                    }
-                }
+        match computer {
+            Some(mut computer) => {
+                let side = computer.0.side().opposite();
+                input.0.set(side, controls.anyone(&match_.0, side));
+                input.0.set(computer.0.side(), computer.0.input(&match_.0));
             }
-
-            if 0.0 < direction.length() {
-                transform.translation += PADDLE_MOVE_SPEED * direction.normalize();
-            }
-
-            if transform.translation.y >= window.height() / 2. - PADDLE_HEIGHT / 2. {
-                transform.translation.y = window.height() / 2. - PADDLE_HEIGHT / 2.;
-            }
-
-            if transform.translation.y <= -window.height() / 2. + PADDLE_HEIGHT / 2. {
-                transform.translation.y = -window.height() / 2. + PADDLE_HEIGHT / 2.;
+            None => {
+                for side in PaddleSide::ALL {
+                    input
+                        .0
+                        .set(side, controls.player(side.index(), &match_.0, side));
+                }
             }
         }
     }
 
-    // need to check for intersections first, then move the ball
-    pub fn move_ball(
-        mut score: ResMut<Score>,
+    pub fn step(
+        input: Res<PlayerInput>,
+        mut match_: ResMut<Match>,
+        mut previous: ResMut<PreviousTick>,
+        recording: Option<ResMut<replay::Recording>>,
+        mut gameplay: events::GameplayEvents,
         mut next_state: ResMut<NextState<GameState>>,
-        mut query: Query<(&mut BallDirection, &mut Transform)>,
-        window_query: Query<&Window>,
     ) {
-        let window = window_query.single().unwrap();
-        let (mut direction, mut transform) = query.single_mut().unwrap();
-
-        if transform.translation.y + BALL_RADIUS >= (window.height() / 2.) {
-            direction.0.y = -1.;
+        // several ticks can run in one frame, don't keep playing after a point
+        if matches!(*next_state, NextState::Pending(_)) {
+            return;
         }
-        if transform.translation.y - BALL_RADIUS <= -(window.height() / 2.) {
-            direction.0.y = 1.;
+
+        previous.0 = match_.0.clone();
+        if let Some(mut recording) = recording {
+            recording.0.record(input.0);
         }
-        if transform.translation.x + BALL_RADIUS >= (window.width() / 2.) {
-            next_state.set(GameState::PointScored);
-            score.0 .0 += 1;
-            if score.0 .0 == POINTS_TO_WIN {
+        let scored = match_.0.step(&input.0);
+        gameplay.send_all(&match_.0);
+        if scored.is_some() {
+            // the winner is picked up from the events, see `game_over::record_winner`
+            if match_.0.winner().is_some() {
                 next_state.set(GameState::GameOver);
+            } else {
+                next_state.set(GameState::PointScored);
             }
         }
-        if transform.translation.x - BALL_RADIUS <= -(window.width() / 2.) {
-            next_state.set(GameState::PointScored);
-            score.0 .1 += 1;
-            if score.0 .1 == POINTS_TO_WIN {
-                next_state.set(GameState::GameOver);
+    }
+
+    pub fn update_countdown(
+        match_: Res<Match>,
+        mode: Res<Mode>,
+        local_side: Option<Res<net::LocalSide>>,
+        mut query: Query<&mut Text2d, With<ServeCountdown>>,
+    ) {
+        // online, nothing is being served until the server has paired us up
+        let countdown = match_
+            .0
+            .serve_countdown()
+            .filter(|_| !online(mode) || local_side.is_some());
+        let text = countdown.map_or_else(String::new, |beat| beat.to_string());
+        for mut countdown_text in &mut query {
+            if countdown_text.0 != text {
+                countdown_text.0 = text.clone();
             }
         }
-        transform.translation += BALL_SPEED * direction.0.normalize();
     }
 
-    pub fn handle_collision(
-        paddles_query: Query<(&Paddle, &Transform)>,
-        mut ball_query: Query<(&mut BallDirection, &Transform)>,
+    pub fn sync_transforms(
+        fixed_time: Res<Time<Fixed>>,
+        match_: Res<Match>,
+        previous: Res<PreviousTick>,
+        mut paddles_query: Query<(&Paddle, &mut Transform), Without<Ball>>,
+        mut ball_query: Query<&mut Transform, With<Ball>>,
     ) {
-        let (mut ball_direction, ball_transform) = ball_query.single_mut().unwrap();
-        for (paddle_side, paddle_transform) in paddles_query {
-            if ball_transform.translation.y + BALL_RADIUS
-                <= paddle_transform.translation.y + PADDLE_HEIGHT
-                && ball_transform.translation.y - BALL_RADIUS
-                    >= paddle_transform.translation.y - PADDLE_HEIGHT
-            {
-                match paddle_side.0 {
-                    PaddleSide::Left => {
-                        if ball_transform.translation.x + BALL_RADIUS
-                            <= paddle_transform.translation.x + PADDLE_WIDTH
-                        {
-                            ball_direction.0.x = 1.0;
-                        }
-                    }
-                    PaddleSide::Right => {
-                        if ball_transform.translation.x - BALL_RADIUS
-                            >= paddle_transform.translation.x - PADDLE_WIDTH
-                        {
-                            ball_direction.0.x = -1.0;
-                        }
-                    }
-                }
-            }
+        let alpha = fixed_time.overstep_fraction();
+        for (paddle, mut transform) in &mut paddles_query {
+            let from = previous.0.paddle(paddle.0).position;
+            let to = match_.0.paddle(paddle.0).position;
+            transform.translation = from.lerp(to, alpha).extend(0.);
         }
+        let mut transform = ball_query.single_mut().unwrap();
+        transform.translation = previous
+            .0
+            .ball
+            .position
+            .lerp(match_.0.ball.position, alpha)
+            .extend(0.);
     }
 }
 
@@ -260,31 +412,203 @@ mod point_scored {
     }
 }
 
+// Local matches only, an online opponent would keep playing. The match's
+// entities stay up behind the overlay, see the InGame systems in `main`.
+mod paused {
+    use crate::*;
+    use game::ai::Ai;
+
+    #[derive(Clone, Copy, PartialEq)]
+    enum Choice {
+        Resume,
+        Restart,
+        Quit,
+    }
+
+    impl Choice {
+        const ALL: [Choice; 3] = [Choice::Resume, Choice::Restart, Choice::Quit];
+
+        fn label(self) -> &'static str {
+            match self {
+                Choice::Resume => "Resume",
+                Choice::Restart => "Restart",
+                Choice::Quit => "Quit to menu",
+            }
+        }
+    }
+
+    // index into `Choice::ALL` of the highlighted line
+    #[derive(Resource, Default)]
+    pub struct Selected(usize);
+
+    #[derive(Component)]
+    pub struct PausedText;
+
+    pub fn pause(keys: Res<ButtonInput<KeyCode>>, mut next_state: ResMut<NextState<GameState>>) {
+        if keys.just_pressed(KeyCode::Escape) {
+            next_state.set(GameState::Paused);
+        }
+    }
+
+    pub fn spawn(
+        mut commands: Commands,
+        assets: Res<assets::GameAssets>,
+        mut meshes: ResMut<Assets<Mesh>>,
+        mut materials: ResMut<Assets<ColorMaterial>>,
+        settings: Res<settings::Settings>,
+    ) {
+        commands.insert_resource(Selected::default());
+        // dims the frozen match underneath
+        commands.spawn((
+            Mesh2d(meshes.add(Rectangle::new(ARENA_WIDTH, ARENA_HEIGHT))),
+            MeshMaterial2d(materials.add(Color::BLACK.with_alpha(0.6))),
+            Transform::from_translation(Vec3::new(0., 0., 2.)),
+            cleanup::PausedCleanup,
+        ));
+        commands.spawn((
+            PausedText,
+            Text2d::default(),
+            assets.text_font(settings.font_size),
+            TextLayout::new_with_justify(JustifyText::Center),
+            Transform::from_translation(Vec3::new(0., 0., 3.)),
+            cleanup::PausedCleanup,
+        ));
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn handle_input(
+        mut commands: Commands,
+        keys: Res<ButtonInput<KeyCode>>,
+        time: Res<Time<Real>>,
+        mut selected: ResMut<Selected>,
+        match_: Res<Match>,
+        mut previous: ResMut<ingame::PreviousTick>,
+        computer: Option<ResMut<ingame::Computer>>,
+        recording: Option<ResMut<replay::Recording>>,
+        mut started: EventWriter<events::MatchStarted>,
+        mut next_state: ResMut<NextState<GameState>>,
+    ) {
+        if keys.just_pressed(KeyCode::Escape) {
+            next_state.set(GameState::InGame);
+            return;
+        }
+        if keys.just_pressed(KeyCode::ArrowUp) {
+            selected.0 = selected.0.saturating_sub(1);
+        }
+        if keys.just_pressed(KeyCode::ArrowDown) {
+            selected.0 = (selected.0 + 1).min(Choice::ALL.len() - 1);
+        }
+        if !keys.just_pressed(KeyCode::Enter) {
+            return;
+        }
+
+        match Choice::ALL[selected.0] {
+            Choice::Resume => next_state.set(GameState::InGame),
+            Choice::Restart => {
+                // same rules and opponent, new serves
+                let seed = time.elapsed().as_nanos() as u64;
+                let config = match_.0.config;
+                previous.0 = Simulation::with_config(seed, config);
+                commands.insert_resource(Match(previous.0.clone()));
+                started.write(events::MatchStarted);
+                if let Some(mut recording) = recording {
+                    recording.0 = game::replay::Replay::new(seed, config);
+                }
+                if let Some(mut computer) = computer {
+                    let ai = &computer.0;
+                    computer.0 = Ai::new(ai.side(), ai.difficulty(), seed);
+                }
+                commands.remove_resource::<Winner>();
+                next_state.set(GameState::InGame);
+            }
+            Choice::Quit => next_state.set(GameState::Menu),
+        }
+    }
+
+    pub fn update_text(selected: Res<Selected>, mut query: Query<&mut Text2d, With<PausedText>>) {
+        let mut text = "Paused\n\n".to_string();
+        for (i, choice) in Choice::ALL.into_iter().enumerate() {
+            if i == selected.0 {
+                text += &format!("< {} >\n", choice.label());
+            } else {
+                text += &format!("{}\n", choice.label());
+            }
+        }
+        for mut paused_text in &mut query {
+            if paused_text.0 != text {
+                paused_text.0 = text.clone();
+            }
+        }
+    }
+}
+
 mod game_over {
     use crate::*;
-    pub fn spawn(mut commands: Commands, asset_server: Res<AssetServer>, score: Res<Score>) {
-        // NOTE: should probably have a resource for this in a bigger project
-        let font = asset_server.load("fonts/FiraSans-Bold.ttf");
-        let text_font = TextFont {
-            font: font.clone(),
-            font_size: FONT_SIZE,
-            ..default()
-        };
+    #[allow(clippy::too_many_arguments)]
+    pub fn spawn(
+        mut commands: Commands,
+        assets: Res<assets::GameAssets>,
+        winner: Option<Res<Winner>>,
+        local_side: Option<Res<net::LocalSide>>,
+        computer: Option<Res<ingame::Computer>>,
+        connection_error: Option<Res<net::ConnectionError>>,
+        match_: Res<Match>,
+        stats: Res<stats::Stats>,
+        settings: Res<settings::Settings>,
+    ) {
+        let text_font = assets.text_font(settings.font_size);
 
-        let game_over_text = if score.0 .0 == POINTS_TO_WIN {
-            Text2d::new("Player 1 won")
-        } else {
-            Text2d::new("Player 2 won")
+        // against the computer the player has the other paddle
+        let local_side = local_side
+            .map(|side| side.0)
+            .or_else(|| computer.map(|computer| computer.0.side().opposite()));
+        let game_over_text = match (&winner, local_side) {
+            (None, _) => match connection_error {
+                Some(error) => error.0.clone(),
+                None => "Connection lost".to_string(),
+            },
+            (Some(winner), Some(local_side)) if winner.0 == local_side => "You won".to_string(),
+            (Some(_), Some(_)) => "You lost".to_string(),
+            (Some(winner), None) if winner.0 == PaddleSide::Left => "Player 1 won".to_string(),
+            (Some(_), None) => "Player 2 won".to_string(),
+        };
+        let game_over_text = match winner {
+            Some(_) => format!(
+                "{}\n{}\n\n{}",
+                game_over_text,
+                breakdown(&match_.0),
+                stats.summary()
+            ),
+            None => game_over_text,
         };
 
         commands.spawn((
-            game_over_text,
+            Text2d::new(game_over_text),
             text_font.clone(),
             TextLayout::new_with_justify(JustifyText::Center),
             Transform::from_translation(Vec3::new(0., 0., 0.)),
             cleanup::GameOverCleanup,
         ));
     }
+
+    // wherever the match was won, locally or on the server
+    pub fn record_winner(mut commands: Commands, mut won: EventReader<events::MatchWon>) {
+        if let Some(won) = won.read().last() {
+            commands.insert_resource(Winner(won.side));
+        }
+    }
+
+    // the score of every game played, and of the one cut short if time ran out
+    fn breakdown(sim: &Simulation) -> String {
+        let unfinished = Some(sim.score).filter(|&score| score != game::Score::default());
+        sim.games
+            .iter()
+            .chain(&unfinished)
+            .map(|game| format!("{} - {}", game.left, game.right))
+            .collect::<Vec<_>>()
+            .join("   ")
+    }
+
     pub fn handle_input(
         buttons: Res<ButtonInput<MouseButton>>,
         mut next_state: ResMut<NextState<GameState>>,
@@ -299,39 +623,114 @@ mod menu_to_ingame {
     use crate::*;
 
     #[derive(Component)]
-    pub struct CurrScore(Score);
+    pub struct ScoreText;
 
     pub fn spawn(
         mut commands: Commands,
-        asset_server: Res<AssetServer>,
-        window_query: Query<&Window>,
+        time: Res<Time<Real>>,
+        mode: Res<Mode>,
+        settings: Res<settings::Settings>,
+        mut started: EventWriter<events::MatchStarted>,
     ) {
-        let window = window_query.single().unwrap();
-
-        let font = asset_server.load("fonts/FiraSans-Bold.ttf");
-        let text_font = TextFont {
-            font: font.clone(),
-            font_size: FONT_SIZE,
-            ..default()
+        // when exactly the player clicked is as good a seed as any, online
+        // matches are served by the server anyway
+        let seed = time.elapsed().as_nanos() as u64;
+        commands.remove_resource::<Winner>();
+        // the server doesn't know about our settings, it sends the rules once
+        // the match starts
+        let online = online(mode);
+        let config = if online {
+            game::Config::default()
+        } else {
+            settings.config()
         };
+        commands.insert_resource(Match(Simulation::with_config(seed, config)));
+        started.write(events::MatchStarted);
+        // the server records online matches
+        if !online {
+            commands.insert_resource(replay::Recording(game::replay::Replay::new(seed, config)));
+        }
+    }
 
-        commands.insert_resource(Score((0, 0)));
+    #[derive(Component)]
+    pub struct ClockText;
+
+    // also shown when watching a replay
+    pub fn spawn_score_text(
+        mut commands: Commands,
+        assets: Res<assets::GameAssets>,
+        settings: Res<settings::Settings>,
+    ) {
+        let text_font = assets.text_font(settings.font_size);
         commands.spawn((
-            CurrScore(Score((0, 0))),
-            Text2d::new("0 - 0"),
+            ScoreText,
+            Text2d::default(),
             text_font.clone(),
             TextLayout::new_with_justify(JustifyText::Center),
-            Transform::from_translation(Vec3::new(0., window.height() / 2.5, 0.)),
+            Transform::from_translation(Vec3::new(0., ARENA_HEIGHT / 2.5, 0.)),
             cleanup::MenuToInGameCleanup,
         ));
+        commands.spawn((
+            ClockText,
+            Text2d::default(),
+            text_font.clone(),
+            TextLayout::new_with_justify(JustifyText::Center),
+            Transform::from_translation(Vec3::new(ARENA_WIDTH / 4., ARENA_HEIGHT / 2.5, 0.)),
+            cleanup::MenuToInGameCleanup,
+        ));
+    }
+
+    // Only redrawn when the score changes. That's on points and games won,
+    // when a new match starts and when a replay is seeked. Online, the rules
+    // arrive along with our side.
+    pub fn update(
+        mut started: EventReader<events::MatchStarted>,
+        mut seeked: EventReader<events::Seeked>,
+        mut points: EventReader<events::PointScored>,
+        mut games: EventReader<events::GameWon>,
+        match_: Res<Match>,
+        local_side: Option<Res<net::LocalSide>>,
+        mut query: Query<&mut Text2d, With<ScoreText>>,
+    ) {
+        let replaced = started.read().count() + seeked.read().count() > 0;
+        let scored = points.read().count() + games.read().count() > 0;
+        let joined = local_side.is_some_and(|local_side| local_side.is_added());
+        if !replaced && !scored && !joined {
+            return;
+        }
+        let text = score_text(&match_.0);
+        for mut shown in &mut query {
+            shown.0 = text.clone();
+        }
+    }
+
+    // points in the current game, then the games won when there's more than one
+    fn score_text(sim: &Simulation) -> String {
+        let rules = sim.config.rules;
+        let games = rules.games_won(&sim.games);
+        let games = if rules.best_of > 1 {
+            format!("Games {} - {}", games.left, games.right)
+        } else {
+            String::new()
+        };
+        format!("{} - {}\n{}", sim.score.left, sim.score.right, games)
     }
 
-    // NOTE: this should be using events
-    pub fn update(score: Res<Score>, mut query: Query<(&mut CurrScore, &mut Text2d)>) {
-        let (mut curr_score, mut score_text) = query.single_mut().unwrap();
-        if score.0 != curr_score.0 .0 {
-            curr_score.0 .0 = score.0;
-            *score_text = Text2d::new(format!("{} - {}", curr_score.0 .0 .0, curr_score.0 .0 .1))
+    // unlike the score this changes with every tick, under a time limit
+    pub fn update_clock(match_: Res<Match>, mut query: Query<&mut Text2d, With<ClockText>>) {
+        let sim = &match_.0;
+        let text = if sim.sudden_death() {
+            "Sudden death".to_string()
+        } else if let Some(ticks) = sim.time_left() {
+            let seconds = ticks.div_ceil(game::TICK_RATE as u32);
+            format!("{}:{:02}", seconds / 60, seconds % 60)
+        } else {
+            String::new()
+        };
+        for mut clock_text in &mut query {
+            if clock_text.0 != text {
+                clock_text.0 = text.clone();
+            }
         }
     }
 }
@@ -342,80 +741,743 @@ fn debug(
     time: Res<Time>,
     mut timer: ResMut<DebugTimer>,
     query: Query<(&ingame::Paddle, &Transform)>,
+    connection: Option<Res<net::Connection>>,
 ) {
     if timer.0.tick(time.delta()).just_finished() {
         for (_, transform) in query {
-            println!("[DEBUG] {:?}", transform.translation);
+            debug!("paddle at {:?}", transform.translation);
+        }
+        if let Some(rtt) = connection.and_then(|connection| connection.rtt) {
+            debug!("rtt {:?}", rtt);
         }
     }
 }
 
+// `client --connect <addr>` plays online against whoever the server pairs us with
+#[cfg(not(target_arch = "wasm32"))]
+fn mode() -> Mode {
+    let mut args = std::env::args().skip(1);
+    match (args.next().as_deref(), args.next()) {
+        (Some("--connect"), Some(addr)) => Mode::Online(addr),
+        (Some("--connect"), None) => Mode::Online(format!("127.0.0.1:{}", protocol::DEFAULT_PORT)),
+        _ => Mode::Local,
+    }
+}
+
+// in the browser it's `?connect=<addr>`, where the address is the server's
+// websocket port and defaults to the host the page came from
+#[cfg(target_arch = "wasm32")]
+fn mode() -> Mode {
+    let location = web_sys::window().unwrap().location();
+    let search = location.search().unwrap_or_default();
+    let Some(param) = search
+        .trim_start_matches('?')
+        .split('&')
+        .find(|param| param.split('=').next() == Some("connect"))
+    else {
+        return Mode::Local;
+    };
+    match param.split_once('=') {
+        Some((_, addr)) if !addr.is_empty() => Mode::Online(addr.to_string()),
+        _ => Mode::Online(format!(
+            "{}:{}",
+            location.hostname().unwrap_or_default(),
+            protocol::DEFAULT_WS_PORT
+        )),
+    }
+}
+
 fn main() {
     App::new()
-        .add_plugins(DefaultPlugins)
+        .add_plugins(
+            DefaultPlugins
+                .set(WindowPlugin {
+                    primary_window: Some(Window {
+                        title: "Pong".into(),
+                        // on the web the canvas fills whatever the page gives it
+                        canvas: Some("#pong".into()),
+                        fit_canvas_to_parent: true,
+                        ..default()
+                    }),
+                    ..default()
+                })
+                .set(AssetPlugin {
+                    // web servers answer missing .meta files with errors or
+                    // html, and we don't have any
+                    meta_check: AssetMetaCheck::Never,
+                    ..default()
+                }),
+        )
+        .add_plugins(PongPlugin)
+        .add_plugins(audio::add)
+        .insert_resource(mode())
+        .insert_resource(controls::Bindings::load())
+        .insert_resource(settings::Settings::load())
+        .insert_resource(replay::file())
         .insert_resource(DebugTimer(Timer::new(
             Duration::from_secs(1),
             TimerMode::Repeating,
         )))
-        .init_state::<GameState>()
-        .add_systems(Startup, |mut commands: Commands| {
-            commands.spawn(Camera2d);
-        })
-        .add_systems(OnEnter(GameState::Menu), menu::spawn)
-        .add_systems(
-            Update,
-            (menu::handle_input).run_if(in_state(GameState::Menu)),
-        )
-        .add_systems(
-            OnExit(GameState::Menu),
-            cleanup_system::<cleanup::MenuCleanup>,
-        )
-        .add_systems(OnEnter(GameState::InGame), ingame::spawn)
-        .add_systems(
-            Update,
-            (
-                ingame::handle_input,
-                ingame::move_ball,
-                ingame::handle_collision,
-            )
-                .run_if(in_state(GameState::InGame)),
-        )
-        .add_systems(
-            OnExit(GameState::InGame),
-            cleanup_system::<cleanup::InGameCleanup>,
-        )
-        .add_systems(OnEnter(GameState::PointScored), point_scored::spawn)
-        .add_systems(
-            Update,
-            (point_scored::wait).run_if(in_state(GameState::PointScored)),
-        )
-        .add_systems(OnEnter(GameState::GameOver), game_over::spawn)
-        .add_systems(
-            Update,
-            (game_over::handle_input).run_if(in_state(GameState::GameOver)),
-        )
-        .add_systems(
-            OnExit(GameState::GameOver),
-            cleanup_system::<cleanup::GameOverCleanup>,
-        )
-        .add_systems(
-            OnTransition {
-                exited: GameState::Menu,
-                entered: GameState::InGame,
-            },
-            menu_to_ingame::spawn,
-        )
-        .add_systems(
-            Update,
-            (menu_to_ingame::update).run_if(in_state(GameState::PointScored)),
-        )
-        .add_systems(
-            OnTransition {
-                exited: GameState::InGame,
-                entered: GameState::GameOver,
-            },
-            cleanup_system::<cleanup::MenuToInGameCleanup>,
-        )
         .add_systems(Update, debug)
         .run();
 }
+
+// The game's states and systems, without the window, assets or anything read
+// from the command line or disk, so tests can run it headless.
+struct PongPlugin;
+
+impl Plugin for PongPlugin {
+    fn build(&self, app: &mut App) {
+        events::add(app);
+        app.insert_resource(Time::<Fixed>::from_hz(game::TICK_RATE as f64))
+            .init_state::<GameState>()
+            .init_resource::<ingame::PlayerInput>()
+            .init_resource::<controls::Pointer>()
+            .init_resource::<stats::Stats>()
+            .init_resource::<net::InterpolationDelay>()
+            .add_systems(
+                Startup,
+                (arena::spawn_cameras, arena::spawn_borders, effects::setup),
+            )
+            .add_systems(Update, arena::fit_viewport)
+            .add_systems(
+                Update,
+                (
+                    stats::reset.run_if(on_event::<events::MatchStarted>),
+                    stats::count,
+                )
+                    .chain(),
+            )
+            .add_systems(OnEnter(GameState::Loading), assets::load)
+            .add_systems(Update, assets::wait.run_if(in_state(GameState::Loading)))
+            .add_systems(
+                OnExit(GameState::Loading),
+                (
+                    cleanup_system::<cleanup::LoadingCleanup>,
+                    audio::start_music,
+                ),
+            )
+            .add_systems(
+                Update,
+                audio::play_effects.run_if(not(in_state(GameState::Loading))),
+            )
+            .add_systems(
+                Update,
+                audio::apply_volume.run_if(resource_changed::<settings::Settings>),
+            )
+            .add_systems(OnEnter(GameState::Menu), menu::spawn)
+            .add_systems(
+                Update,
+                (menu::handle_input).run_if(in_state(GameState::Menu)),
+            )
+            .add_systems(
+                Update,
+                (menu::handle_computer_input)
+                    .run_if(in_state(GameState::Menu))
+                    .run_if(not(online)),
+            )
+            .add_systems(
+                Update,
+                (menu::handle_lobby_input)
+                    .run_if(in_state(GameState::Menu))
+                    .run_if(online),
+            )
+            .add_systems(
+                Update,
+                (replay::watch)
+                    .run_if(in_state(GameState::Menu))
+                    .run_if(not(online)),
+            )
+            .add_systems(
+                OnExit(GameState::Menu),
+                cleanup_system::<cleanup::MenuCleanup>,
+            )
+            .add_systems(OnEnter(GameState::Settings), settings::spawn)
+            .add_systems(
+                Update,
+                (settings::handle_input, settings::update_text)
+                    .chain()
+                    .run_if(in_state(GameState::Settings)),
+            )
+            .add_systems(
+                OnExit(GameState::Settings),
+                cleanup_system::<cleanup::SettingsCleanup>,
+            )
+            .add_systems(
+                Update,
+                settings::apply_window_mode.run_if(resource_changed::<settings::Settings>),
+            )
+            .add_systems(OnEnter(GameState::Controls), controls::spawn)
+            .add_systems(
+                Update,
+                (controls::rebind, controls::update_text)
+                    .chain()
+                    .run_if(in_state(GameState::Controls)),
+            )
+            .add_systems(
+                OnExit(GameState::Controls),
+                cleanup_system::<cleanup::ControlsCleanup>,
+            )
+            .add_systems(Update, controls::track_pointer)
+            // pausing leaves the match on screen, so coming back from it doesn't
+            // spawn it again and going to it doesn't clean it up
+            .add_systems(
+                OnEnter(GameState::InGame),
+                ingame::spawn.run_if(not(any_with_component::<ingame::Ball>)),
+            )
+            .add_systems(
+                FixedUpdate,
+                (ingame::handle_input, ingame::step)
+                    .chain()
+                    .run_if(in_state(GameState::InGame))
+                    .run_if(not(online)),
+            )
+            .add_systems(
+                Update,
+                (ingame::sync_transforms, ingame::update_countdown)
+                    .run_if(in_state(GameState::InGame).or(in_state(GameState::Replay))),
+            )
+            .add_systems(
+                Update,
+                effects::spawn_trail
+                    .after(ingame::sync_transforms)
+                    .run_if(in_state(GameState::InGame).or(in_state(GameState::Replay)))
+                    .run_if(effects::enabled),
+            )
+            // turning the effects off lets whatever is showing play out
+            .add_systems(
+                Update,
+                (effects::paddle_hit, effects::start_shake).run_if(effects::enabled),
+            )
+            .add_systems(
+                Update,
+                (
+                    effects::update_particles,
+                    effects::update_flash,
+                    effects::update_shake,
+                ),
+            )
+            .add_systems(
+                OnExit(GameState::InGame),
+                cleanup_system::<cleanup::InGameCleanup>.run_if(not(in_state(GameState::Paused))),
+            )
+            .add_systems(
+                Update,
+                (paused::pause)
+                    .run_if(in_state(GameState::InGame))
+                    .run_if(not(online)),
+            )
+            .add_systems(OnEnter(GameState::Paused), paused::spawn)
+            .add_systems(
+                Update,
+                (paused::handle_input, paused::update_text)
+                    .chain()
+                    .run_if(in_state(GameState::Paused)),
+            )
+            .add_systems(
+                OnExit(GameState::Paused),
+                (
+                    cleanup_system::<cleanup::PausedCleanup>,
+                    // quitting takes the match down with it
+                    (
+                        cleanup_system::<cleanup::InGameCleanup>,
+                        cleanup_system::<cleanup::MenuToInGameCleanup>,
+                    )
+                        .run_if(not(in_state(GameState::InGame))),
+                ),
+            )
+            .add_systems(OnEnter(GameState::PointScored), point_scored::spawn)
+            .add_systems(
+                FixedUpdate,
+                (point_scored::wait)
+                    .run_if(in_state(GameState::PointScored))
+                    .run_if(not(online)),
+            )
+            // online the winner comes from the server, before the game over
+            // it goes with is entered
+            .add_systems(
+                Update,
+                game_over::record_winner
+                    .after(net::receive)
+                    .run_if(not(in_state(GameState::Replay))),
+            )
+            .add_systems(OnEnter(GameState::GameOver), game_over::spawn)
+            .add_systems(
+                Update,
+                (game_over::handle_input).run_if(in_state(GameState::GameOver)),
+            )
+            .add_systems(
+                OnExit(GameState::GameOver),
+                cleanup_system::<cleanup::GameOverCleanup>,
+            )
+            .add_systems(
+                OnTransition {
+                    exited: GameState::Menu,
+                    entered: GameState::InGame,
+                },
+                (
+                    menu_to_ingame::spawn,
+                    menu_to_ingame::spawn_score_text,
+                    arena::spawn_center_line,
+                    (net::connect, net::spawn_waiting_text).run_if(online),
+                ),
+            )
+            .add_systems(
+                Update,
+                (menu_to_ingame::update, menu_to_ingame::update_clock).run_if(
+                    in_state(GameState::InGame)
+                        .or(in_state(GameState::PointScored))
+                        .or(in_state(GameState::Replay)),
+                ),
+            )
+            // the match is kept once it's over, or quit from the pause menu
+            .add_systems(
+                OnEnter(GameState::GameOver),
+                replay::save.run_if(resource_exists::<replay::Recording>),
+            )
+            .add_systems(
+                OnEnter(GameState::Menu),
+                replay::save.run_if(resource_exists::<replay::Recording>),
+            )
+            .add_systems(
+                OnEnter(GameState::Replay),
+                (
+                    ingame::spawn,
+                    replay::spawn,
+                    menu_to_ingame::spawn_score_text,
+                    arena::spawn_center_line,
+                ),
+            )
+            .add_systems(
+                Update,
+                (replay::handle_input, replay::update_text)
+                    .chain()
+                    .run_if(in_state(GameState::Replay)),
+            )
+            .add_systems(
+                FixedUpdate,
+                (replay::play).run_if(in_state(GameState::Replay)),
+            )
+            .add_systems(
+                OnExit(GameState::Replay),
+                (
+                    cleanup_system::<cleanup::InGameCleanup>,
+                    cleanup_system::<cleanup::MenuToInGameCleanup>,
+                    cleanup_system::<cleanup::ReplayCleanup>,
+                ),
+            )
+            // online matches can also end during PointScored, when a player leaves
+            .add_systems(
+                OnEnter(GameState::GameOver),
+                cleanup_system::<cleanup::MenuToInGameCleanup>,
+            )
+            .add_systems(
+                FixedUpdate,
+                (net::send_input)
+                    .run_if(in_state(GameState::InGame))
+                    .run_if(resource_exists::<net::Connection>),
+            )
+            .add_systems(
+                Update,
+                (net::receive, net::ping).run_if(resource_exists::<net::Connection>),
+            )
+            .add_systems(
+                Update,
+                (net::cancel_waiting)
+                    .run_if(in_state(GameState::InGame))
+                    .run_if(any_with_component::<net::WaitingText>),
+            )
+            // only when giving up on waiting for an opponent
+            .add_systems(
+                OnTransition {
+                    exited: GameState::InGame,
+                    entered: GameState::Menu,
+                },
+                cleanup_system::<cleanup::MenuToInGameCleanup>,
+            )
+            .add_systems(
+                Update,
+                (net::interpolate)
+                    .after(ingame::sync_transforms)
+                    .run_if(in_state(GameState::InGame))
+                    .run_if(resource_exists::<net::Connection>),
+            )
+            .add_systems(OnExit(GameState::GameOver), net::disconnect);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::{
+        input::{
+            ButtonState, InputPlugin,
+            keyboard::{Key, KeyboardInput},
+            mouse::MouseButtonInput,
+        },
+        state::app::StatesPlugin,
+        time::TimeUpdateStrategy,
+    };
+
+    // the game without a window, moving on one tick every update
+    fn app(settings: settings::Settings) -> App {
+        app_in(Mode::Local, settings)
+    }
+
+    fn app_in(mode: Mode, settings: settings::Settings) -> App {
+        let mut app = App::new();
+        app.add_plugins((
+            MinimalPlugins,
+            StatesPlugin,
+            InputPlugin,
+            AssetPlugin::default(),
+        ))
+        .init_asset::<Mesh>()
+        .init_asset::<ColorMaterial>()
+        .init_asset::<Font>()
+        .init_asset::<audio::Sound>()
+        .add_plugins(PongPlugin)
+        .insert_resource(mode)
+        .insert_resource(controls::Bindings::default())
+        .insert_resource(settings)
+        .insert_resource(replay::file())
+        .insert_resource(TimeUpdateStrategy::ManualDuration(
+            Time::<Fixed>::from_hz(game::TICK_RATE as f64).timestep(),
+        ));
+        // there's no font loader without a renderer, so this also goes
+        // through the fallback for assets that fail to load
+        update_until(&mut app, GameState::Menu);
+        app
+    }
+
+    fn state(app: &App) -> GameState {
+        app.world().resource::<State<GameState>>().get().clone()
+    }
+
+    fn count<T: Component>(app: &mut App) -> usize {
+        app.world_mut()
+            .query_filtered::<(), With<T>>()
+            .iter(app.world())
+            .count()
+    }
+
+    fn sim(app: &mut App) -> Mut<'_, Simulation> {
+        app.world_mut()
+            .resource_mut::<Match>()
+            .map_unchanged(|match_| &mut match_.0)
+    }
+
+    fn click(app: &mut App) {
+        for state in [ButtonState::Pressed, ButtonState::Released] {
+            app.world_mut().send_event(MouseButtonInput {
+                button: MouseButton::Left,
+                state,
+                window: Entity::PLACEHOLDER,
+            });
+            app.update();
+        }
+    }
+
+    fn key(app: &mut App, key_code: KeyCode, state: ButtonState) {
+        app.world_mut().send_event(KeyboardInput {
+            key_code,
+            logical_key: Key::Unidentified(bevy::input::keyboard::NativeKey::Unidentified),
+            state,
+            text: None,
+            repeat: false,
+            window: Entity::PLACEHOLDER,
+        });
+        app.update();
+    }
+
+    fn tap(app: &mut App, key_code: KeyCode) {
+        key(app, key_code, ButtonState::Pressed);
+        key(app, key_code, ButtonState::Released);
+    }
+
+    fn update_until(app: &mut App, expected: GameState) {
+        for _ in 0..10 * game::TICK_RATE as usize {
+            if state(app) == expected {
+                return;
+            }
+            app.update();
+        }
+        panic!("stuck in {:?} waiting for {:?}", state(app), expected);
+    }
+
+    fn score_text(app: &mut App) -> String {
+        app.world_mut()
+            .query_filtered::<&Text2d, With<menu_to_ingame::ScoreText>>()
+            .single(app.world())
+            .unwrap()
+            .0
+            .clone()
+    }
+
+    // puts the ball right at the right goal, the left player scores next tick
+    fn about_to_score_left(app: &mut App) {
+        let mut sim = sim(app);
+        sim.serve_ticks = 0;
+        sim.ball.position = Vec2::new(ARENA_WIDTH / 2., 0.);
+        sim.ball.direction = Vec2::X;
+    }
+
+    #[test]
+    fn every_sound_is_made_while_loading() {
+        let app = app(settings::Settings::default());
+        let assets = app.world().resource::<assets::GameAssets>();
+        let sounds = app.world().resource::<Assets<audio::Sound>>();
+        let all = &assets.sounds;
+        for sound in [
+            &all.paddle_hit,
+            &all.wall_hit,
+            &all.point,
+            &all.game_over,
+            &all.music,
+        ] {
+            assert!(sounds.contains(sound));
+        }
+    }
+
+    #[test]
+    fn local_match_goes_from_menu_to_game_over_and_back() {
+        let mut app = app(settings::Settings {
+            points_per_game: 2,
+            ..default()
+        });
+        assert_eq!(state(&app), GameState::Menu);
+        assert!(count::<cleanup::MenuCleanup>(&mut app) > 0);
+
+        click(&mut app);
+        assert_eq!(state(&app), GameState::InGame);
+        assert_eq!(count::<cleanup::MenuCleanup>(&mut app), 0);
+        assert_eq!(count::<ingame::Ball>(&mut app), 1);
+        assert_eq!(count::<ingame::Paddle>(&mut app), 2);
+        assert_eq!(count::<menu_to_ingame::ScoreText>(&mut app), 1);
+
+        about_to_score_left(&mut app);
+        update_until(&mut app, GameState::PointScored);
+        assert_eq!(sim(&mut app).score.left, 1);
+        // the paddles and ball are spawned again for every point
+        assert_eq!(count::<cleanup::InGameCleanup>(&mut app), 0);
+        assert_eq!(count::<menu_to_ingame::ScoreText>(&mut app), 1);
+
+        update_until(&mut app, GameState::InGame);
+        assert_eq!(count::<ingame::Ball>(&mut app), 1);
+        about_to_score_left(&mut app);
+        update_until(&mut app, GameState::GameOver);
+        assert_eq!(
+            app.world().resource::<Winner>().0,
+            PaddleSide::Left,
+            "the left player got to 2 points"
+        );
+        assert_eq!(count::<cleanup::InGameCleanup>(&mut app), 0);
+        assert_eq!(count::<cleanup::MenuToInGameCleanup>(&mut app), 0);
+        assert_eq!(count::<cleanup::GameOverCleanup>(&mut app), 1);
+
+        click(&mut app);
+        assert_eq!(state(&app), GameState::Menu);
+        assert_eq!(count::<cleanup::GameOverCleanup>(&mut app), 0);
+        assert!(count::<cleanup::MenuCleanup>(&mut app) > 0);
+    }
+
+    #[test]
+    fn a_new_match_starts_with_no_score_or_stats() {
+        let mut app = app(settings::Settings {
+            points_per_game: 1,
+            ..default()
+        });
+        let no_stats = stats::Stats::default().summary();
+        for _ in 0..2 {
+            click(&mut app);
+            assert_eq!(state(&app), GameState::InGame);
+            assert!(score_text(&mut app).starts_with("0 - 0"));
+            assert_eq!(app.world().resource::<stats::Stats>().summary(), no_stats);
+
+            about_to_score_left(&mut app);
+            update_until(&mut app, GameState::GameOver);
+            assert_ne!(app.world().resource::<stats::Stats>().summary(), no_stats);
+            click(&mut app);
+            assert_eq!(state(&app), GameState::Menu);
+        }
+    }
+
+    #[test]
+    fn ticks_step_the_match_with_the_players_input() {
+        let mut app = app(settings::Settings::default());
+        click(&mut app);
+        key(&mut app, KeyCode::KeyW, ButtonState::Pressed);
+        // not long enough to reach the wall
+        for _ in 0..10 {
+            app.update();
+        }
+        key(&mut app, KeyCode::KeyW, ButtonState::Released);
+
+        // the left paddle went up at full speed for every tick it was held,
+        // and the recording replays to exactly the match on screen
+        let recording = &app.world().resource::<replay::Recording>().0;
+        let held = recording
+            .inputs
+            .iter()
+            .filter(|input| input.left == 1.)
+            .count();
+        assert!(held >= 10);
+        assert!(recording.inputs.iter().all(|input| input.right == 0.));
+        let played_back = recording.simulate(recording.inputs.len());
+        let paddle_y = held as f32 * game::PADDLE_MOVE_SPEED * game::TICK_DT;
+        assert!((played_back.paddle(PaddleSide::Left).position.y - paddle_y).abs() < 1e-3);
+        assert_eq!(played_back, *sim(&mut app));
+
+        // the ball waits in the middle for the serve countdown, then it's off
+        assert_eq!(sim(&mut app).ball.position, Vec2::ZERO);
+        for _ in 0..game::SERVE_COUNTDOWN * game::SERVE_BEAT_TICKS {
+            app.update();
+        }
+        assert_ne!(sim(&mut app).ball.position, Vec2::ZERO);
+    }
+
+    #[test]
+    fn pausing_keeps_the_match_and_quitting_cleans_it_up() {
+        let mut app = app(settings::Settings::default());
+        click(&mut app);
+        tap(&mut app, KeyCode::Escape);
+        assert_eq!(state(&app), GameState::Paused);
+        assert_eq!(count::<cleanup::PausedCleanup>(&mut app), 2);
+        assert_eq!(count::<ingame::Ball>(&mut app), 1);
+
+        let paused = sim(&mut app).clone();
+        for _ in 0..30 {
+            app.update();
+        }
+        assert_eq!(*sim(&mut app), paused);
+
+        tap(&mut app, KeyCode::Escape);
+        assert_eq!(state(&app), GameState::InGame);
+        assert_eq!(count::<cleanup::PausedCleanup>(&mut app), 0);
+        assert_eq!(count::<ingame::Ball>(&mut app), 1, "not spawned again");
+
+        tap(&mut app, KeyCode::Escape);
+        tap(&mut app, KeyCode::ArrowDown);
+        tap(&mut app, KeyCode::ArrowDown);
+        tap(&mut app, KeyCode::Enter);
+        assert_eq!(state(&app), GameState::Menu);
+        assert_eq!(count::<cleanup::InGameCleanup>(&mut app), 0);
+        assert_eq!(count::<cleanup::MenuToInGameCleanup>(&mut app), 0);
+        assert_eq!(count::<cleanup::PausedCleanup>(&mut app), 0);
+    }
+
+    #[test]
+    fn restarting_starts_the_score_and_stats_over() {
+        let mut app = app(settings::Settings::default());
+        click(&mut app);
+        about_to_score_left(&mut app);
+        update_until(&mut app, GameState::PointScored);
+        update_until(&mut app, GameState::InGame);
+        assert!(score_text(&mut app).starts_with("1 - 0"));
+
+        tap(&mut app, KeyCode::Escape);
+        tap(&mut app, KeyCode::ArrowDown);
+        tap(&mut app, KeyCode::Enter);
+        assert_eq!(state(&app), GameState::InGame);
+        app.update();
+        assert_eq!(sim(&mut app).score, game::Score::default());
+        assert!(score_text(&mut app).starts_with("0 - 0"));
+        assert_eq!(
+            app.world().resource::<stats::Stats>().summary(),
+            stats::Stats::default().summary()
+        );
+    }
+
+    #[test]
+    fn replays_show_the_score_where_they_are() {
+        let mut app = app(settings::Settings::default());
+        // a match where nobody moves, until a little after the first point
+        let mut replay = game::replay::Replay::new(3, game::Config::default());
+        let mut played = replay.simulate(0);
+        while played.score == game::Score::default() {
+            assert!(replay.inputs.len() < 60 * game::TICK_RATE as usize);
+            replay.record(game::Input::default());
+            played.step(&game::Input::default());
+        }
+        let scored = format!("{} - {}", played.score.left, played.score.right);
+        storage::write("scored.replay", &replay.encode());
+        app.insert_resource(replay::ReplayFile("scored.replay".to_string()));
+
+        // straight after a match, which had a score of its own
+        click(&mut app);
+        about_to_score_left(&mut app);
+        update_until(&mut app, GameState::PointScored);
+        update_until(&mut app, GameState::InGame);
+        tap(&mut app, KeyCode::Escape);
+        tap(&mut app, KeyCode::ArrowDown);
+        tap(&mut app, KeyCode::ArrowDown);
+        tap(&mut app, KeyCode::Enter);
+        tap(&mut app, KeyCode::KeyR);
+        assert_eq!(state(&app), GameState::Replay);
+        assert!(score_text(&mut app).starts_with("0 - 0"));
+
+        tap(&mut app, KeyCode::Space);
+        for _ in 0..replay.inputs.len() / (5 * game::TICK_RATE as usize) + 1 {
+            tap(&mut app, KeyCode::ArrowRight);
+        }
+        assert_eq!(*sim(&mut app), replay.simulate(replay.inputs.len()));
+        assert!(score_text(&mut app).starts_with(&scored));
+
+        tap(&mut app, KeyCode::ArrowLeft);
+        assert_eq!(sim(&mut app).score, game::Score::default());
+        assert!(score_text(&mut app).starts_with("0 - 0"));
+    }
+
+    #[test]
+    fn waiting_for_an_opponent_can_be_given_up() {
+        // a server that never gets as far as the handshake
+        let server = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap().to_string();
+        let mut app = app_in(Mode::Online(addr), settings::Settings::default());
+        click(&mut app);
+        assert_eq!(state(&app), GameState::InGame);
+        assert_eq!(count::<net::WaitingText>(&mut app), 1);
+
+        tap(&mut app, KeyCode::Escape);
+        assert_eq!(state(&app), GameState::Menu);
+        assert!(!app.world().contains_resource::<net::Connection>());
+        assert_eq!(count::<cleanup::InGameCleanup>(&mut app), 0);
+        assert_eq!(count::<cleanup::MenuToInGameCleanup>(&mut app), 0);
+        assert!(count::<cleanup::MenuCleanup>(&mut app) > 0);
+    }
+
+    #[test]
+    fn visual_effects_play_out_and_can_be_turned_off() {
+        let mut on = app(settings::Settings::default());
+        click(&mut on);
+        on.world_mut().send_event(events::PaddleHit {
+            side: PaddleSide::Left,
+        });
+        on.update();
+        assert!(count::<effects::Particle>(&mut on) > 0);
+        assert_eq!(count::<effects::Flash>(&mut on), 1);
+        // a second of play is long enough for any of them to be gone
+        tap(&mut on, KeyCode::Escape);
+        for _ in 0..game::TICK_RATE as usize {
+            on.update();
+        }
+        assert_eq!(count::<effects::Particle>(&mut on), 0);
+        assert_eq!(count::<effects::Flash>(&mut on), 0);
+
+        let mut off = app(settings::Settings {
+            visual_effects: false,
+            ..default()
+        });
+        click(&mut off);
+        off.world_mut().send_event(events::PaddleHit {
+            side: PaddleSide::Left,
+        });
+        off.world_mut().send_event(events::PointScored {
+            side: PaddleSide::Left,
+        });
+        off.update();
+        assert_eq!(count::<effects::Particle>(&mut off), 0, "no trail either");
+        assert_eq!(count::<effects::Flash>(&mut off), 0);
+        assert!(off.world().resource::<effects::Shake>().0.is_none());
+    }
+}
@@ -0,0 +1,351 @@
+// Online play: the server runs the match, we only send our paddle input and
+// draw whatever it sends back.
+
+use std::{collections::VecDeque, time::Duration};
+
+use bevy::{platform::time::Instant, prelude::*};
+use game::{PaddleSide, Simulation};
+use protocol::{ClientMessage, RoomCode, ServerMessage};
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender, error::TryRecvError};
+
+use crate::{
+    GameState, Match, Mode, assets::GameAssets, cleanup, controls, events::GameplayEvents, ingame,
+    settings,
+};
+
+mod interpolation;
+pub use interpolation::{InterpolationDelay, interpolate};
+
+// browsers can't open TCP sockets, they talk to the server's websocket port
+#[cfg(not(target_arch = "wasm32"))]
+mod tcp;
+#[cfg(not(target_arch = "wasm32"))]
+use tcp as transport;
+#[cfg(target_arch = "wasm32")]
+mod websocket;
+#[cfg(target_arch = "wasm32")]
+use websocket as transport;
+
+const PING_INTERVAL: Duration = Duration::from_secs(1);
+
+// Handle to the network task. Dropping it closes the connection.
+#[derive(Resource)]
+pub struct Connection {
+    incoming: UnboundedReceiver<protocol::Result<ServerMessage>>,
+    outgoing: UnboundedSender<ClientMessage>,
+    // inputs already applied to our paddle that the server hasn't
+    // acknowledged yet, replayed on top of every snapshot
+    pending_inputs: VecDeque<(u32, f32)>,
+    snapshots: interpolation::SnapshotBuffer,
+    last_sequence: u32,
+    ping_timer: Timer,
+    // pings carry the milliseconds since this instant
+    epoch: Instant,
+    pub rtt: Option<Duration>,
+}
+
+// why the last online match ended early, shown on the game over screen
+#[derive(Resource)]
+pub struct ConnectionError(pub String);
+
+// the paddle this client controls, known once the server welcomes us
+#[derive(Resource)]
+pub struct LocalSide(pub PaddleSide);
+
+#[derive(Component)]
+pub struct WaitingText;
+
+const CANCEL_HINT: &str = "Escape: back to the menu";
+
+// what to ask the lobby for once connected, a quick match if there's none
+#[derive(Resource)]
+pub enum RoomRequest {
+    Create,
+    Join(RoomCode),
+}
+
+pub fn connect(
+    mut commands: Commands,
+    mode: Res<Mode>,
+    room_request: Option<Res<RoomRequest>>,
+    settings: Res<settings::Settings>,
+) {
+    let Mode::Online(addr) = &*mode else {
+        return;
+    };
+
+    let (incoming_tx, incoming) = mpsc::unbounded_channel();
+    let (outgoing, outgoing_rx) = mpsc::unbounded_channel();
+    transport::spawn(addr.clone(), incoming_tx, outgoing_rx);
+    // goes out right after the handshake
+    let _ = outgoing.send(match room_request.as_deref() {
+        None => ClientMessage::QuickMatch,
+        Some(RoomRequest::Create) => ClientMessage::CreateRoom(settings.rules()),
+        Some(RoomRequest::Join(code)) => ClientMessage::JoinRoom(*code),
+    });
+
+    commands.remove_resource::<RoomRequest>();
+    commands.remove_resource::<LocalSide>();
+    commands.remove_resource::<ConnectionError>();
+    commands.insert_resource(Connection {
+        incoming,
+        outgoing,
+        pending_inputs: VecDeque::new(),
+        snapshots: default(),
+        last_sequence: 0,
+        ping_timer: Timer::new(PING_INTERVAL, TimerMode::Repeating),
+        epoch: Instant::now(),
+        rtt: None,
+    });
+}
+
+pub fn ping(time: Res<Time>, mut connection: ResMut<Connection>) {
+    if connection.ping_timer.tick(time.delta()).just_finished() {
+        let now = connection.epoch.elapsed().as_millis() as u32;
+        let _ = connection.outgoing.send(ClientMessage::Ping(now));
+    }
+}
+
+pub fn spawn_waiting_text(
+    mut commands: Commands,
+    assets: Res<GameAssets>,
+    settings: Res<settings::Settings>,
+) {
+    commands.spawn((
+        WaitingText,
+        Text2d::new(format!("Waiting for an opponent\n{}", CANCEL_HINT)),
+        assets.text_font(settings.font_size),
+        TextLayout::new_with_justify(JustifyText::Center),
+        Transform::from_translation(Vec3::new(0., 0., 1.)),
+        cleanup::InGameCleanup,
+    ));
+}
+
+// Nobody has joined yet, closing the connection gives up our place in the
+// lobby
+pub fn cancel_waiting(
+    mut commands: Commands,
+    keys: Res<ButtonInput<KeyCode>>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if keys.just_pressed(KeyCode::Escape) {
+        commands.remove_resource::<Connection>();
+        next_state.set(GameState::Menu);
+    }
+}
+
+// Our paddle answers to either set of keys, the other one belongs to the
+// server. Input is applied right away instead of waiting for the server to
+// echo it back, so our paddle feels as responsive as in a local match.
+pub fn send_input(
+    controls: controls::Controls,
+    local_side: Option<Res<LocalSide>>,
+    mut connection: ResMut<Connection>,
+    mut match_: ResMut<Match>,
+    mut previous: ResMut<ingame::PreviousTick>,
+) {
+    let Some(local_side) = local_side else {
+        return;
+    };
+
+    let direction = controls.anyone(&match_.0, local_side.0);
+
+    // one input per tick, the server applies them one tick at a time too
+    connection.last_sequence += 1;
+    let sequence = connection.last_sequence;
+    connection.pending_inputs.push_back((sequence, direction));
+    let _ = connection.outgoing.send(ClientMessage::Input {
+        sequence,
+        direction,
+    });
+
+    previous.0 = match_.0.clone();
+    match_.0.move_paddle(local_side.0, direction);
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn receive(
+    mut commands: Commands,
+    mut connection: ResMut<Connection>,
+    mut match_: ResMut<Match>,
+    mut previous: Option<ResMut<ingame::PreviousTick>>,
+    local_side: Option<Res<LocalSide>>,
+    state: Res<State<GameState>>,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut waiting_text: Query<(Entity, &mut Text2d), With<WaitingText>>,
+    mut gameplay: GameplayEvents,
+) {
+    loop {
+        let message = match connection.incoming.try_recv() {
+            Ok(Ok(message)) => message,
+            Ok(Err(e)) => {
+                warn!("connection error: {}", e);
+                commands.insert_resource(ConnectionError(format!("Connection lost\n{}", e)));
+                commands.remove_resource::<Connection>();
+                next_state.set(GameState::GameOver);
+                return;
+            }
+            Err(TryRecvError::Empty) => return,
+            Err(TryRecvError::Disconnected) => {
+                warn!("lost connection to the server");
+                commands.remove_resource::<Connection>();
+                next_state.set(GameState::GameOver);
+                return;
+            }
+        };
+
+        match message {
+            ServerMessage::Welcome(side, rules) => {
+                info!("playing as the {} paddle", side);
+                commands.insert_resource(LocalSide(side));
+                match_.0.config.rules = rules;
+                for (entity, _) in &waiting_text {
+                    commands.entity(entity).despawn();
+                }
+            }
+            ServerMessage::RoomCreated(code) => {
+                for (_, mut text) in &mut waiting_text {
+                    *text = Text2d::new(format!(
+                        "Room {}\nWaiting for an opponent\n{}",
+                        code, CANCEL_HINT
+                    ));
+                }
+            }
+            ServerMessage::RoomNotFound(code) => {
+                commands.insert_resource(ConnectionError(format!("There is no room {}", code)));
+                commands.remove_resource::<Connection>();
+                next_state.set(GameState::GameOver);
+                return;
+            }
+            ServerMessage::Snapshot(snapshot) => {
+                snapshot.apply(&mut match_.0);
+                if let Some(local_side) = &local_side {
+                    let acknowledged = snapshot.last_inputs[local_side.0.index()];
+                    let pending = &mut connection.pending_inputs;
+                    reconcile(pending, &mut match_.0, local_side.0, acknowledged);
+                }
+
+                connection.snapshots.push(snapshot);
+
+                // only our own paddle is drawn from the simulation, see
+                // `interpolate` for everything else
+                if let Some(previous) = previous.as_mut() {
+                    let predicted = local_side
+                        .as_ref()
+                        .filter(|_| *state.get() == GameState::InGame)
+                        .map(|local_side| *previous.0.paddle(local_side.0));
+                    previous.0 = match_.0.clone();
+                    if let Some(paddle) = predicted {
+                        previous.0.paddles[paddle.side.index()] = paddle;
+                    }
+                }
+                // the server goes quiet while a point is being celebrated
+                if *state.get() == GameState::PointScored {
+                    next_state.set(GameState::InGame);
+                }
+            }
+            ServerMessage::GameWon(score) => {
+                match_.0.games.push(score);
+                if let Some(winner) = match_.0.config.rules.game_winner(score) {
+                    gameplay.send(game::Event::GameWon(winner));
+                }
+            }
+            ServerMessage::PointScored(scorer) => {
+                gameplay.send(game::Event::PointScored(scorer));
+                next_state.set(GameState::PointScored);
+            }
+            ServerMessage::PaddleHit(side) => gameplay.send(game::Event::PaddleHit(side)),
+            ServerMessage::WallHit => gameplay.send(game::Event::WallHit),
+            ServerMessage::GameOver(winner) => {
+                gameplay.send(game::Event::MatchWon(winner));
+                commands.remove_resource::<Connection>();
+                next_state.set(GameState::GameOver);
+                return;
+            }
+            ServerMessage::Pong(sent) => {
+                let now = connection.epoch.elapsed().as_millis() as u32;
+                connection.rtt = Some(Duration::from_millis(now.wrapping_sub(sent) as u64));
+            }
+            ServerMessage::Hello { .. } | ServerMessage::Ping(_) => {}
+        }
+    }
+}
+
+// Rewinds our paddle to where the server has it after the acknowledged input
+// and replays whatever input it hasn't seen yet.
+fn reconcile(
+    pending: &mut VecDeque<(u32, f32)>,
+    sim: &mut Simulation,
+    local_side: PaddleSide,
+    acknowledged: u32,
+) {
+    while pending
+        .front()
+        .is_some_and(|&(sequence, _)| sequence <= acknowledged)
+    {
+        pending.pop_front();
+    }
+    for &(_, direction) in pending.iter() {
+        sim.move_paddle(local_side, direction);
+    }
+}
+
+pub fn disconnect(mut commands: Commands) {
+    commands.remove_resource::<Connection>();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIDE: PaddleSide = PaddleSide::Left;
+
+    fn inputs() -> VecDeque<(u32, f32)> {
+        VecDeque::from([(1, 1.), (2, 1.), (3, -0.5), (4, 1.)])
+    }
+
+    // a match with our paddle moved by `inputs`
+    fn moved(inputs: &[(u32, f32)]) -> Simulation {
+        let mut sim = Simulation::new(0);
+        for &(_, direction) in inputs {
+            sim.move_paddle(SIDE, direction);
+        }
+        sim
+    }
+
+    #[test]
+    fn acknowledged_inputs_are_dropped() {
+        let mut pending = inputs();
+        reconcile(&mut pending, &mut Simulation::new(0), SIDE, 2);
+        assert_eq!(pending, [(3, -0.5), (4, 1.)]);
+
+        reconcile(&mut pending, &mut Simulation::new(0), SIDE, 4);
+        assert!(pending.is_empty());
+    }
+
+    #[test]
+    fn unacknowledged_inputs_are_replayed_on_the_server_state() {
+        let mut pending = inputs();
+        // the server has our paddle somewhere else than we predicted
+        let mut sim = Simulation::new(0);
+        sim.paddles[SIDE.index()].position.y = -100.;
+        reconcile(&mut pending, &mut sim, SIDE, 2);
+
+        let replayed = game::PADDLE_MOVE_SPEED * game::TICK_DT * 0.5;
+        let y = sim.paddle(SIDE).position.y;
+        assert!((y - (-100. + replayed)).abs() < 1e-3);
+        // the other paddle is left to the server
+        assert_eq!(sim.paddle(SIDE.opposite()).position.y, 0.);
+    }
+
+    #[test]
+    fn a_correct_prediction_is_left_alone() {
+        let all: Vec<_> = inputs().into();
+        let predicted = moved(&all);
+
+        let mut pending = inputs();
+        let mut from_server = moved(&all[..2]);
+        reconcile(&mut pending, &mut from_server, SIDE, 2);
+        assert_eq!(from_server.paddles, predicted.paddles);
+    }
+}
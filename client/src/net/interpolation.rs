@@ -0,0 +1,213 @@
+// The opponent's paddle and the ball are drawn a little in the past, blending
+// between the two snapshots around that moment, so late or bunched up packets
+// don't make them stutter. Our own paddle is predicted instead, see
+// `send_input`.
+
+use std::{collections::VecDeque, time::Duration};
+
+use bevy::prelude::*;
+use game::TICK_RATE;
+use protocol::Snapshot;
+
+use super::{Connection, LocalSide};
+use crate::ingame;
+
+// how quickly the render clock is pulled towards where it should be, per second
+const CATCH_UP_RATE: f64 = 2.;
+
+// further off than this and the render clock just jumps, like after a point
+// when the server went quiet for a while
+const MAX_DRIFT_TICKS: f64 = TICK_RATE as f64 / 2.;
+
+// How far behind the newest snapshot to draw. Longer hides more jitter and
+// loss but makes the opponent and ball lag further behind. Fixed at the
+// default, it isn't one of the settings.
+#[derive(Resource)]
+pub struct InterpolationDelay(pub Duration);
+
+impl Default for InterpolationDelay {
+    fn default() -> Self {
+        InterpolationDelay(Duration::from_millis(100))
+    }
+}
+
+#[derive(Default)]
+pub struct SnapshotBuffer {
+    snapshots: VecDeque<Snapshot>,
+    // server tick being drawn, between two snapshots most of the time
+    render_tick: Option<f64>,
+}
+
+impl SnapshotBuffer {
+    pub fn push(&mut self, snapshot: Snapshot) {
+        if self
+            .snapshots
+            .back()
+            .is_some_and(|newest| newest.tick >= snapshot.tick)
+        {
+            return;
+        }
+        self.snapshots.push_back(snapshot);
+    }
+
+    fn advance(&mut self, dt: f64, delay_ticks: f64) {
+        let Some(newest) = self.snapshots.back() else {
+            return;
+        };
+        let target = newest.tick as f64 - delay_ticks;
+
+        let render_tick = match self.render_tick {
+            Some(render_tick) if (target - render_tick).abs() <= MAX_DRIFT_TICKS => {
+                let render_tick = render_tick + dt * TICK_RATE as f64;
+                render_tick + (target - render_tick) * (dt * CATCH_UP_RATE).min(1.)
+            }
+            _ => target,
+        };
+        self.render_tick = Some(render_tick);
+
+        // keep one snapshot at or before the render tick to blend from
+        while self
+            .snapshots
+            .get(1)
+            .is_some_and(|next| next.tick as f64 <= render_tick)
+        {
+            self.snapshots.pop_front();
+        }
+    }
+
+    // the snapshot to draw, blended between its neighbours
+    fn sample(&self) -> Option<Snapshot> {
+        let render_tick = self.render_tick?;
+        let from = *self.snapshots.front()?;
+        let Some(to) = self.snapshots.get(1) else {
+            return Some(from);
+        };
+        if render_tick <= from.tick as f64 {
+            return Some(from);
+        }
+        // a point was scored in between, the ball shouldn't fly back across
+        // the arena to where it restarts
+        if from.score != to.score {
+            return Some(*to);
+        }
+
+        let alpha = ((render_tick - from.tick as f64) / (to.tick - from.tick) as f64) as f32;
+        let mut blended = *to;
+        for (blended, from) in blended.paddles.iter_mut().zip(from.paddles) {
+            *blended = from + (*blended - from) * alpha;
+        }
+        blended.ball_position = from.ball_position.lerp(to.ball_position, alpha);
+        Some(blended)
+    }
+}
+
+// runs after `ingame::sync_transforms` and overrides everything but our paddle
+pub fn interpolate(
+    time: Res<Time>,
+    delay: Res<InterpolationDelay>,
+    local_side: Option<Res<LocalSide>>,
+    mut connection: ResMut<Connection>,
+    mut paddles_query: Query<(&ingame::Paddle, &mut Transform), Without<ingame::Ball>>,
+    mut ball_query: Query<&mut Transform, With<ingame::Ball>>,
+) {
+    let buffer = &mut connection.snapshots;
+    buffer.advance(
+        time.delta_secs_f64(),
+        delay.0.as_secs_f64() * TICK_RATE as f64,
+    );
+    let Some(snapshot) = buffer.sample() else {
+        return;
+    };
+
+    let local_side = local_side.map(|local_side| local_side.0);
+    for (paddle, mut transform) in &mut paddles_query {
+        if Some(paddle.0) != local_side {
+            transform.translation.y = snapshot.paddles[paddle.0.index()];
+        }
+    }
+    let mut transform = ball_query.single_mut().unwrap();
+    transform.translation = snapshot.ball_position.extend(0.);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use game::{Score, Vec2};
+
+    fn snapshot(tick: u32, x: f32, score: Score) -> Snapshot {
+        Snapshot {
+            tick,
+            paddles: [x, -x],
+            ball_position: Vec2::new(x, 0.),
+            ball_direction: Vec2::X,
+            ball_speed: 1.,
+            serve_ticks: 0,
+            clock_ticks: tick,
+            score,
+            last_inputs: [0; 2],
+        }
+    }
+
+    fn buffer(snapshots: &[Snapshot]) -> SnapshotBuffer {
+        let mut buffer = SnapshotBuffer::default();
+        for &snapshot in snapshots {
+            buffer.push(snapshot);
+        }
+        buffer
+    }
+
+    #[test]
+    fn draws_between_the_snapshots_around_the_render_tick() {
+        let score = Score::default();
+        let mut buffer = buffer(&[snapshot(10, 0., score), snapshot(20, 100., score)]);
+        buffer.advance(0., 5.);
+        assert_eq!(buffer.render_tick, Some(15.));
+
+        let sampled = buffer.sample().unwrap();
+        assert_eq!(sampled.ball_position, Vec2::new(50., 0.));
+        assert_eq!(sampled.paddles, [50., -50.]);
+    }
+
+    #[test]
+    fn the_render_clock_eases_back_unless_it_is_too_far_off() {
+        let score = Score::default();
+        let mut buffer = buffer(&[snapshot(10, 0., score), snapshot(20, 100., score)]);
+        buffer.advance(0., 5.);
+
+        // a little ahead of schedule, drawing keeps going at the tick rate
+        // and only creeps towards the new target
+        buffer.push(snapshot(22, 120., score));
+        let frame = 1. / TICK_RATE as f64;
+        buffer.advance(frame, 5.);
+        let render_tick = buffer.render_tick.unwrap();
+        assert!(render_tick > 16. && render_tick < 17.);
+
+        // way ahead, like after the server went quiet for a point
+        buffer.push(snapshot(100, 800., score));
+        buffer.advance(frame, 5.);
+        assert_eq!(buffer.render_tick, Some(95.));
+        assert_eq!(buffer.snapshots.front().unwrap().tick, 22);
+    }
+
+    #[test]
+    fn a_point_in_between_skips_to_the_restart() {
+        let scored = Score { left: 1, right: 0 };
+        let mut buffer = buffer(&[
+            snapshot(10, 600., Score::default()),
+            snapshot(20, 0., scored),
+        ]);
+        buffer.advance(0., 5.);
+        assert_eq!(buffer.sample().unwrap().ball_position, Vec2::ZERO);
+    }
+
+    #[test]
+    fn old_and_repeated_snapshots_are_ignored() {
+        let score = Score::default();
+        let buffer = buffer(&[
+            snapshot(10, 0., score),
+            snapshot(10, 50., score),
+            snapshot(5, 50., score),
+        ]);
+        assert_eq!(buffer.snapshots.len(), 1);
+    }
+}
@@ -0,0 +1,53 @@
+use protocol::{ClientMessage, ServerMessage};
+use tokio::{
+    net::TcpStream,
+    sync::mpsc::{UnboundedReceiver, UnboundedSender},
+};
+
+pub fn spawn(
+    addr: String,
+    incoming: UnboundedSender<protocol::Result<ServerMessage>>,
+    outgoing: UnboundedReceiver<ClientMessage>,
+) {
+    // bevy doesn't run a tokio reactor, so the socket lives on its own thread
+    std::thread::spawn(move || {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        runtime.block_on(async move {
+            if let Err(e) = run(addr, &incoming, outgoing).await {
+                let _ = incoming.send(Err(e));
+            }
+        });
+    });
+}
+
+async fn run(
+    addr: String,
+    incoming: &UnboundedSender<protocol::Result<ServerMessage>>,
+    mut outgoing: UnboundedReceiver<ClientMessage>,
+) -> protocol::Result<()> {
+    let mut connection = protocol::Connection::new(TcpStream::connect(&addr).await?);
+    connection.client_handshake().await?;
+    let (mut reader, mut writer) = connection.into_split();
+
+    loop {
+        tokio::select! {
+            message = reader.read_message() => match message? {
+                Some(ServerMessage::Ping(n)) => writer.write_message(&ClientMessage::Pong(n)).await?,
+                Some(message) => {
+                    if incoming.send(Ok(message)).is_err() {
+                        return Ok(());
+                    }
+                }
+                None => return Ok(()),
+            },
+            message = outgoing.recv() => match message {
+                Some(message) => writer.write_message(&message).await?,
+                // bevy dropped the connection
+                None => return Ok(()),
+            },
+        }
+    }
+}
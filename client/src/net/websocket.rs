@@ -0,0 +1,86 @@
+use std::io;
+
+use futures_util::{FutureExt, SinkExt, Stream, StreamExt, select};
+use gloo_net::websocket::{Message, WebSocketError, futures::WebSocket};
+use protocol::{
+    ClientMessage, PROTOCOL_VERSION, ServerMessage, check_version, decode_payload, encode_payload,
+    expect_hello,
+};
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+
+pub fn spawn(
+    addr: String,
+    incoming: UnboundedSender<protocol::Result<ServerMessage>>,
+    outgoing: UnboundedReceiver<ClientMessage>,
+) {
+    // the browser's event loop drives the socket, no thread needed
+    wasm_bindgen_futures::spawn_local(async move {
+        if let Err(e) = run(addr, &incoming, outgoing).await {
+            let _ = incoming.send(Err(e));
+        }
+    });
+}
+
+async fn run(
+    addr: String,
+    incoming: &UnboundedSender<protocol::Result<ServerMessage>>,
+    mut outgoing: UnboundedReceiver<ClientMessage>,
+) -> protocol::Result<()> {
+    let socket = WebSocket::open(&format!("ws://{}", addr))
+        .map_err(|e| protocol::Error::Io(io::Error::other(e.to_string())))?;
+    let (mut writer, reader) = socket.split();
+    let mut reader = reader.fuse();
+
+    // same handshake as `Connection::client_handshake`, one payload per message
+    let hello = ClientMessage::Hello {
+        version: PROTOCOL_VERSION,
+    };
+    writer
+        .send(to_message(&hello)?)
+        .await
+        .map_err(to_protocol_error)?;
+    check_version(expect_hello(read_message(&mut reader).await)?)?;
+
+    loop {
+        select! {
+            message = read_message(&mut reader).fuse() => match message? {
+                Some(ServerMessage::Ping(n)) => {
+                    let pong = to_message(&ClientMessage::Pong(n))?;
+                    writer.send(pong).await.map_err(to_protocol_error)?;
+                }
+                Some(message) => {
+                    if incoming.send(Ok(message)).is_err() {
+                        return Ok(());
+                    }
+                }
+                None => return Ok(()),
+            },
+            message = outgoing.recv().fuse() => match message {
+                Some(message) => writer.send(to_message(&message)?).await.map_err(to_protocol_error)?,
+                // bevy dropped the connection
+                None => return Ok(()),
+            },
+        }
+    }
+}
+
+async fn read_message(
+    reader: &mut (impl Stream<Item = Result<Message, WebSocketError>> + Unpin),
+) -> protocol::Result<Option<ServerMessage>> {
+    loop {
+        match reader.next().await {
+            Some(Ok(Message::Bytes(payload))) => return decode_payload(&payload).map(Some),
+            Some(Ok(Message::Text(_))) => continue,
+            Some(Err(WebSocketError::ConnectionClose(_))) | None => return Ok(None),
+            Some(Err(e)) => return Err(to_protocol_error(e)),
+        }
+    }
+}
+
+fn to_message(message: &ClientMessage) -> protocol::Result<Message> {
+    Ok(Message::Bytes(encode_payload(message)?.to_vec()))
+}
+
+fn to_protocol_error(e: WebSocketError) -> protocol::Error {
+    protocol::Error::Io(io::Error::other(e.to_string()))
+}
@@ -0,0 +1,294 @@
+// Recording local matches and watching them back. Online matches are recorded
+// by the server instead, when it's given a `--replays` directory, and can be
+// watched with `client --replay <file>`.
+//
+// Playback steps the match through the recorded inputs, so the viewer shows
+// exactly what happened. Seeking back starts from the last checkpoint before
+// where it's going, kept every few seconds as the match is played through.
+
+use bevy::prelude::*;
+use game::{ARENA_HEIGHT, Simulation, TICK_RATE, replay::Replay};
+
+use crate::{
+    GameState, Match,
+    assets::GameAssets,
+    cleanup,
+    events::{GameplayEvents, MatchStarted, Seeked},
+    ingame::PreviousTick,
+    settings::Settings,
+    storage,
+};
+
+// where the last local match is kept, and what the menu plays by default
+const LAST_REPLAY_FILE: &str = "last.replay";
+
+const SEEK_TICKS: usize = 5 * TICK_RATE as usize;
+const CHECKPOINT_TICKS: usize = 10 * TICK_RATE as usize;
+const SPEEDS: [f32; 5] = [0.25, 0.5, 1., 2., 4.];
+const NORMAL_SPEED: usize = 2;
+
+// the match being played, one input per tick that was stepped
+#[derive(Resource)]
+pub struct Recording(pub Replay);
+
+// keeps the match that just ended, or was quit, for the menu to play back
+pub fn save(mut commands: Commands, recording: Res<Recording>) {
+    storage::write(LAST_REPLAY_FILE, &recording.0.encode());
+    commands.remove_resource::<Recording>();
+}
+
+// the replay the menu plays
+#[derive(Resource)]
+pub struct ReplayFile(pub String);
+
+// `client --replay <file>` plays a file other than the last local match
+#[cfg(not(target_arch = "wasm32"))]
+pub fn file() -> ReplayFile {
+    let args: Vec<_> = std::env::args().skip(1).collect();
+    let file = args
+        .iter()
+        .position(|arg| arg == "--replay")
+        .and_then(|i| args.get(i + 1));
+    ReplayFile(file.map_or(LAST_REPLAY_FILE.to_string(), |file| file.clone()))
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn file() -> ReplayFile {
+    ReplayFile(LAST_REPLAY_FILE.to_string())
+}
+
+#[derive(Resource)]
+pub struct Playback {
+    replay: Replay,
+    // the match every `CHECKPOINT_TICKS` ticks, as far as it's been played
+    checkpoints: Vec<Simulation>,
+    // ticks of the replay `Match` has been stepped through
+    tick: usize,
+    speed: usize,
+    paused: bool,
+    // fraction of a tick the playback speed has built up
+    progress: f32,
+}
+
+impl Playback {
+    fn new(replay: Replay) -> Self {
+        Playback {
+            checkpoints: vec![replay.simulate(0)],
+            replay,
+            tick: 0,
+            speed: NORMAL_SPEED,
+            paused: false,
+            progress: 0.,
+        }
+    }
+
+    fn seek(&mut self, sim: &mut Simulation, tick: usize) {
+        let tick = tick.min(self.replay.inputs.len());
+        if tick < self.tick {
+            let checkpoint = (tick / CHECKPOINT_TICKS).min(self.checkpoints.len() - 1);
+            *sim = self.checkpoints[checkpoint].clone();
+            self.tick = checkpoint * CHECKPOINT_TICKS;
+        }
+        while self.tick < tick {
+            sim.step(&self.replay.inputs[self.tick]);
+            self.tick += 1;
+            if self.tick == self.checkpoints.len() * CHECKPOINT_TICKS {
+                self.checkpoints.push(sim.clone());
+            }
+        }
+    }
+}
+
+// R in the menu, starts playing `ReplayFile` if there is one
+pub fn watch(
+    mut commands: Commands,
+    keys: Res<ButtonInput<KeyCode>>,
+    file: Res<ReplayFile>,
+    mut started: EventWriter<MatchStarted>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if !keys.just_pressed(KeyCode::KeyR) {
+        return;
+    }
+    let Some(text) = storage::read(&file.0) else {
+        info!("no replay to watch at {}", file.0);
+        return;
+    };
+    let replay = match Replay::decode(&text) {
+        Ok(replay) => replay,
+        Err(e) => {
+            warn!("couldn't read replay {}: {}", file.0, e);
+            return;
+        }
+    };
+    let playback = Playback::new(replay);
+    commands.insert_resource(Match(playback.checkpoints[0].clone()));
+    commands.insert_resource(playback);
+    started.write(MatchStarted);
+    next_state.set(GameState::Replay);
+}
+
+#[derive(Component)]
+pub struct PlaybackText;
+
+pub fn spawn(mut commands: Commands, assets: Res<GameAssets>, settings: Res<Settings>) {
+    commands.spawn((
+        PlaybackText,
+        Text2d::default(),
+        assets.text_font(settings.font_size / 2.),
+        TextLayout::new_with_justify(JustifyText::Center),
+        Transform::from_translation(Vec3::new(0., -ARENA_HEIGHT / 2.5, 1.)),
+        cleanup::ReplayCleanup,
+    ));
+}
+
+pub fn play(
+    mut playback: ResMut<Playback>,
+    mut match_: ResMut<Match>,
+    mut previous: ResMut<PreviousTick>,
+    mut gameplay: GameplayEvents,
+) {
+    previous.0 = match_.0.clone();
+    if playback.paused {
+        return;
+    }
+    playback.progress += SPEEDS[playback.speed];
+    while playback.progress >= 1. {
+        playback.progress -= 1.;
+        if playback.tick == playback.replay.inputs.len() {
+            break;
+        }
+        let tick = playback.tick + 1;
+        playback.seek(&mut match_.0, tick);
+        gameplay.send_all(&match_.0);
+    }
+    if playback.tick == playback.replay.inputs.len() {
+        playback.paused = true;
+    }
+}
+
+// Space pauses, Left and Right seek, Up and Down change the speed and while
+// paused Comma and Period step a single tick
+pub fn handle_input(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut playback: ResMut<Playback>,
+    mut match_: ResMut<Match>,
+    mut previous: ResMut<PreviousTick>,
+    mut seeked: EventWriter<Seeked>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if keys.just_pressed(KeyCode::Escape) {
+        next_state.set(GameState::Menu);
+        return;
+    }
+    if keys.just_pressed(KeyCode::Space) {
+        let at_end = playback.tick == playback.replay.inputs.len();
+        if playback.paused && at_end {
+            seek(&mut playback, &mut match_, &mut previous, 0);
+            seeked.write(Seeked);
+        }
+        playback.paused = !playback.paused;
+        playback.progress = 0.;
+    }
+    if keys.just_pressed(KeyCode::ArrowUp) {
+        playback.speed = (playback.speed + 1).min(SPEEDS.len() - 1);
+    }
+    if keys.just_pressed(KeyCode::ArrowDown) {
+        playback.speed = playback.speed.saturating_sub(1);
+    }
+
+    let tick = playback.tick;
+    let target = if keys.just_pressed(KeyCode::ArrowLeft) {
+        tick.saturating_sub(SEEK_TICKS)
+    } else if keys.just_pressed(KeyCode::ArrowRight) {
+        tick + SEEK_TICKS
+    } else if playback.paused && keys.just_pressed(KeyCode::Comma) {
+        tick.saturating_sub(1)
+    } else if playback.paused && keys.just_pressed(KeyCode::Period) {
+        tick + 1
+    } else {
+        return;
+    };
+    seek(&mut playback, &mut match_, &mut previous, target);
+    seeked.write(Seeked);
+}
+
+// Jumps rather than plays to `tick`, without any gameplay events along the
+// way. Whatever shows the match redraws on `Seeked` instead.
+fn seek(playback: &mut Playback, match_: &mut Match, previous: &mut PreviousTick, tick: usize) {
+    playback.seek(&mut match_.0, tick);
+    // straight there rather than sliding over from where we were
+    previous.0 = match_.0.clone();
+}
+
+pub fn update_text(playback: Res<Playback>, mut query: Query<&mut Text2d, With<PlaybackText>>) {
+    let status = if playback.paused { "   Paused" } else { "" };
+    let text = format!(
+        "{} / {}   {}x{}\n\
+         Space: pause   Left/Right: seek   Up/Down: speed   , and .: step   Escape: quit",
+        clock(playback.tick),
+        clock(playback.replay.inputs.len()),
+        SPEEDS[playback.speed],
+        status,
+    );
+    for mut playback_text in &mut query {
+        if playback_text.0 != text {
+            playback_text.0 = text.clone();
+        }
+    }
+}
+
+// ticks of play as m:ss
+fn clock(ticks: usize) -> String {
+    let seconds = ticks / TICK_RATE as usize;
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use game::{Config, Input, PaddleSide};
+
+    // long enough for a few checkpoints, with the left paddle wandering about
+    fn playback() -> Playback {
+        let mut replay = Replay::new(7, Config::default());
+        for tick in 0..CHECKPOINT_TICKS * 3 + 10 {
+            let mut input = Input::default();
+            input.set(PaddleSide::Left, if tick % 90 < 45 { 1. } else { -1. });
+            replay.record(input);
+        }
+        Playback::new(replay)
+    }
+
+    #[test]
+    fn seeking_back_starts_from_the_last_checkpoint() {
+        let mut playback = playback();
+        let mut sim = playback.checkpoints[0].clone();
+        let end = playback.replay.inputs.len();
+        playback.seek(&mut sim, end);
+        assert_eq!(playback.checkpoints.len(), 4);
+
+        for tick in [CHECKPOINT_TICKS * 2 + 5, CHECKPOINT_TICKS, 3, 0, end] {
+            playback.seek(&mut sim, tick);
+            assert_eq!(playback.tick, tick);
+            assert_eq!(sim, playback.replay.simulate(tick), "at tick {}", tick);
+        }
+        assert_eq!(playback.checkpoints.len(), 4, "no checkpoint kept twice");
+    }
+
+    #[test]
+    fn checkpoints_are_kept_as_far_as_the_match_was_played() {
+        let mut playback = playback();
+        let mut sim = playback.checkpoints[0].clone();
+        playback.seek(&mut sim, CHECKPOINT_TICKS + 1);
+        assert_eq!(playback.checkpoints.len(), 2);
+
+        // back to the first, then past the ones not kept yet
+        playback.seek(&mut sim, 1);
+        playback.seek(&mut sim, CHECKPOINT_TICKS * 3);
+        assert_eq!(playback.checkpoints.len(), 4);
+        for (i, checkpoint) in playback.checkpoints.iter().enumerate() {
+            assert_eq!(*checkpoint, playback.replay.simulate(i * CHECKPOINT_TICKS));
+        }
+    }
+}
@@ -0,0 +1,443 @@
+// Tuning that used to be hardcoded, loaded from a file at startup and changed
+// from the settings screen. Online matches are played with the defaults,
+// apart from the rules of rooms we open.
+
+use bevy::{
+    prelude::*,
+    window::{MonitorSelection, PrimaryWindow, WindowMode as BevyWindowMode},
+};
+use game::rules::Rules;
+use serde::{Deserialize, Serialize};
+
+use crate::{GameState, assets::GameAssets, cleanup, storage};
+
+const SETTINGS_FILE: &str = "settings.ron";
+
+const MAX_POINTS_PER_GAME: usize = 21;
+const MAX_BEST_OF: usize = 7;
+const TIME_LIMIT_STEP: u32 = 60;
+const MAX_TIME_LIMIT: u32 = 10 * 60;
+const BALL_SPEED_STEP: f32 = 300.;
+const MIN_BALL_SPEED: f32 = 900.;
+const MAX_BALL_SPEED: f32 = 3000.;
+// not on the settings screen, only there for editing the file
+const MIN_PADDLE_MOVE_SPEED: f32 = 300.;
+const MAX_PADDLE_MOVE_SPEED: f32 = 3000.;
+const MIN_FONT_SIZE: f32 = 20.;
+const MAX_FONT_SIZE: f32 = 80.;
+const VOLUME_STEP: u32 = 10;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WindowMode {
+    #[default]
+    Windowed,
+    Fullscreen,
+}
+
+// anything missing from the file keeps its default
+#[derive(Resource, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub points_per_game: usize,
+    pub win_by_two: bool,
+    pub best_of: usize,
+    /// In seconds, 0 for none.
+    pub time_limit: u32,
+    pub ball_speed: f32,
+    pub paddle_move_speed: f32,
+    pub font_size: f32,
+    pub window_mode: WindowMode,
+    /// Percent, 0 turns the music off.
+    pub music_volume: u32,
+    /// Percent, for everything but the music.
+    pub effects_volume: u32,
+    pub muted: bool,
+    /// The ball's trail, hit particles, paddle flashes and screen shake.
+    pub visual_effects: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        let config = game::Config::default();
+        Settings {
+            points_per_game: config.rules.points_per_game,
+            win_by_two: config.rules.win_by_two,
+            best_of: config.rules.best_of,
+            time_limit: config
+                .rules
+                .time_limit
+                .map_or(0, |ticks| ticks / game::TICK_RATE as u32),
+            ball_speed: config.ball_speed,
+            paddle_move_speed: config.paddle_move_speed,
+            font_size: 50.,
+            window_mode: WindowMode::default(),
+            music_volume: 50,
+            effects_volume: 80,
+            muted: false,
+            visual_effects: true,
+        }
+    }
+}
+
+impl Settings {
+    pub fn load() -> Self {
+        let settings = storage::read(SETTINGS_FILE)
+            .and_then(|saved| ron::from_str::<Settings>(&saved).ok())
+            .unwrap_or_default()
+            .in_range();
+        // the same check the rules of rooms and replays go through
+        match settings.rules().validate() {
+            Ok(()) => settings,
+            Err(reason) => {
+                warn!(
+                    "using the default rules, the saved ones are invalid: {}",
+                    reason
+                );
+                let default = Settings::default();
+                Settings {
+                    points_per_game: default.points_per_game,
+                    win_by_two: default.win_by_two,
+                    best_of: default.best_of,
+                    time_limit: default.time_limit,
+                    ..settings
+                }
+            }
+        }
+    }
+
+    // A hand edited file can have anything in it. Brought within the same
+    // bounds the settings screen keeps to, so what's shown is what's played.
+    fn in_range(self) -> Self {
+        let default = Settings::default();
+        let within = |value: f32, min, max, default| {
+            if value.is_finite() {
+                value.clamp(min, max)
+            } else {
+                default
+            }
+        };
+        Settings {
+            points_per_game: self.points_per_game.clamp(1, MAX_POINTS_PER_GAME),
+            // odd numbers only, rounded up like a step on the settings screen
+            best_of: (self.best_of.clamp(1, MAX_BEST_OF) | 1).min(MAX_BEST_OF),
+            // whole minutes as shown, to the nearest one but never down to none
+            time_limit: match self.time_limit.min(MAX_TIME_LIMIT) {
+                0 => 0,
+                limit => ((limit + TIME_LIMIT_STEP / 2) / TIME_LIMIT_STEP).max(1) * TIME_LIMIT_STEP,
+            },
+            ball_speed: within(
+                self.ball_speed,
+                MIN_BALL_SPEED,
+                MAX_BALL_SPEED,
+                default.ball_speed,
+            ),
+            paddle_move_speed: within(
+                self.paddle_move_speed,
+                MIN_PADDLE_MOVE_SPEED,
+                MAX_PADDLE_MOVE_SPEED,
+                default.paddle_move_speed,
+            ),
+            font_size: within(
+                self.font_size,
+                MIN_FONT_SIZE,
+                MAX_FONT_SIZE,
+                default.font_size,
+            ),
+            music_volume: self.music_volume.min(100),
+            effects_volume: self.effects_volume.min(100),
+            ..self
+        }
+    }
+
+    fn save(&self) {
+        match ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default()) {
+            Ok(contents) => storage::write(SETTINGS_FILE, &contents),
+            Err(e) => error!("couldn't save the settings: {}", e),
+        }
+    }
+
+    /// What local matches and rooms we open are played by.
+    pub fn rules(&self) -> Rules {
+        Rules {
+            points_per_game: self.points_per_game,
+            win_by_two: self.win_by_two,
+            best_of: self.best_of,
+            time_limit: (self.time_limit > 0).then(|| self.time_limit * game::TICK_RATE as u32),
+        }
+    }
+
+    /// Linear volume the music plays at, after muting.
+    pub fn music_volume(&self) -> f32 {
+        if self.muted {
+            0.
+        } else {
+            self.music_volume as f32 / 100.
+        }
+    }
+
+    /// Linear volume sound effects play at, after muting.
+    pub fn effects_volume(&self) -> f32 {
+        if self.muted {
+            0.
+        } else {
+            self.effects_volume as f32 / 100.
+        }
+    }
+
+    /// What local matches are played with.
+    pub fn config(&self) -> game::Config {
+        game::Config {
+            rules: self.rules(),
+            ball_speed: self.ball_speed,
+            paddle_move_speed: self.paddle_move_speed,
+        }
+    }
+}
+
+pub fn apply_window_mode(
+    settings: Res<Settings>,
+    mut windows: Query<&mut Window, With<PrimaryWindow>>,
+) {
+    let mode = match settings.window_mode {
+        WindowMode::Windowed => BevyWindowMode::Windowed,
+        WindowMode::Fullscreen => BevyWindowMode::BorderlessFullscreen(MonitorSelection::Current),
+    };
+    for mut window in &mut windows {
+        if window.mode != mode {
+            window.mode = mode;
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Item {
+    PointsPerGame,
+    WinByTwo,
+    BestOf,
+    TimeLimit,
+    BallSpeed,
+    MusicVolume,
+    EffectsVolume,
+    Mute,
+    VisualEffects,
+    Window,
+    Controls,
+}
+
+impl Item {
+    const ALL: [Item; 11] = [
+        Item::PointsPerGame,
+        Item::WinByTwo,
+        Item::BestOf,
+        Item::TimeLimit,
+        Item::BallSpeed,
+        Item::MusicVolume,
+        Item::EffectsVolume,
+        Item::Mute,
+        Item::VisualEffects,
+        Item::Window,
+        Item::Controls,
+    ];
+
+    fn label(self, settings: &Settings) -> String {
+        match self {
+            Item::PointsPerGame => format!("Games to {} points", settings.points_per_game),
+            Item::WinByTwo if settings.win_by_two => "Win by two".to_string(),
+            Item::WinByTwo => "Win by one".to_string(),
+            Item::BestOf => format!("Best of {}", settings.best_of),
+            Item::TimeLimit if settings.time_limit == 0 => "No time limit".to_string(),
+            Item::TimeLimit => format!("{} minute limit", settings.time_limit / 60),
+            Item::BallSpeed => format!("Ball speed {}", settings.ball_speed),
+            Item::MusicVolume => format!("Music {}", slider(settings.music_volume)),
+            Item::EffectsVolume => format!("Sound effects {}", slider(settings.effects_volume)),
+            Item::Mute if settings.muted => "Sound off".to_string(),
+            Item::Mute => "Sound on".to_string(),
+            Item::VisualEffects if settings.visual_effects => "Visual effects on".to_string(),
+            Item::VisualEffects => "Visual effects off".to_string(),
+            Item::Window => match settings.window_mode {
+                WindowMode::Windowed => "Windowed".to_string(),
+                WindowMode::Fullscreen => "Fullscreen".to_string(),
+            },
+            Item::Controls => "Controls".to_string(),
+        }
+    }
+
+    // steps the setting up or down, returns whether anything changed
+    fn change(self, settings: &mut Settings, step: i32) -> bool {
+        let before = settings.clone();
+        match self {
+            Item::PointsPerGame => {
+                settings.points_per_game = settings
+                    .points_per_game
+                    .saturating_add_signed(step as isize)
+                    .clamp(1, MAX_POINTS_PER_GAME);
+            }
+            Item::WinByTwo => settings.win_by_two = !settings.win_by_two,
+            // odd numbers only, so matches can't end level
+            Item::BestOf => {
+                settings.best_of = settings
+                    .best_of
+                    .saturating_add_signed(2 * step as isize)
+                    .clamp(1, MAX_BEST_OF);
+            }
+            Item::TimeLimit => {
+                settings.time_limit = settings
+                    .time_limit
+                    .saturating_add_signed(step * TIME_LIMIT_STEP as i32)
+                    .min(MAX_TIME_LIMIT);
+            }
+            Item::BallSpeed => {
+                settings.ball_speed = (settings.ball_speed + step as f32 * BALL_SPEED_STEP)
+                    .clamp(MIN_BALL_SPEED, MAX_BALL_SPEED);
+            }
+            Item::MusicVolume => {
+                settings.music_volume = step_volume(settings.music_volume, step);
+            }
+            Item::EffectsVolume => {
+                settings.effects_volume = step_volume(settings.effects_volume, step);
+            }
+            Item::Mute => settings.muted = !settings.muted,
+            Item::VisualEffects => settings.visual_effects = !settings.visual_effects,
+            Item::Window => {
+                settings.window_mode = match settings.window_mode {
+                    WindowMode::Windowed => WindowMode::Fullscreen,
+                    WindowMode::Fullscreen => WindowMode::Windowed,
+                };
+            }
+            Item::Controls => {}
+        }
+        *settings != before
+    }
+}
+
+fn step_volume(volume: u32, step: i32) -> u32 {
+    volume
+        .saturating_add_signed(step * VOLUME_STEP as i32)
+        .min(100)
+}
+
+// a volume in percent as a bar of ten
+fn slider(volume: u32) -> String {
+    let filled = (volume / VOLUME_STEP) as usize;
+    format!("[{}{}]", "=".repeat(filled), "-".repeat(10 - filled))
+}
+
+// index into `Item::ALL` of the highlighted line
+#[derive(Resource, Default)]
+pub struct Selected(usize);
+
+#[derive(Component)]
+pub struct SettingsText;
+
+pub fn spawn(mut commands: Commands, assets: Res<GameAssets>, settings: Res<Settings>) {
+    commands.init_resource::<Selected>();
+    commands.spawn((
+        SettingsText,
+        Text2d::default(),
+        // a little smaller than elsewhere, there are a lot of lines to fit
+        assets.text_font(settings.font_size * 0.8),
+        TextLayout::new_with_justify(JustifyText::Center),
+        cleanup::SettingsCleanup,
+    ));
+}
+
+pub fn handle_input(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut settings: ResMut<Settings>,
+    mut selected: ResMut<Selected>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if keys.just_pressed(KeyCode::Escape) {
+        next_state.set(GameState::Menu);
+        return;
+    }
+
+    if keys.just_pressed(KeyCode::ArrowUp) {
+        selected.0 = selected.0.saturating_sub(1);
+    }
+    if keys.just_pressed(KeyCode::ArrowDown) {
+        selected.0 = (selected.0 + 1).min(Item::ALL.len() - 1);
+    }
+
+    let item = Item::ALL[selected.0];
+    if item == Item::Controls && keys.just_pressed(KeyCode::Enter) {
+        next_state.set(GameState::Controls);
+        return;
+    }
+    let step = i32::from(keys.just_pressed(KeyCode::ArrowRight))
+        - i32::from(keys.just_pressed(KeyCode::ArrowLeft));
+    // only touch the resource on an actual change, it triggers a window update
+    if step != 0 && item.change(settings.bypass_change_detection(), step) {
+        settings.set_changed();
+        settings.save();
+    }
+}
+
+pub fn update_text(
+    settings: Res<Settings>,
+    selected: Res<Selected>,
+    mut query: Query<&mut Text2d, With<SettingsText>>,
+) {
+    let mut text = String::new();
+    for (i, item) in Item::ALL.into_iter().enumerate() {
+        let label = item.label(&settings);
+        if i == selected.0 {
+            text += &format!("< {} >\n", label);
+        } else {
+            text += &format!("{}\n", label);
+        }
+    }
+    text += "\nUp/Down to pick, Left/Right to change\nEscape to go back";
+    for mut settings_text in &mut query {
+        if settings_text.0 != text {
+            settings_text.0 = text.clone();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hand_edited_settings_are_brought_in_range() {
+        storage::write(
+            SETTINGS_FILE,
+            "(points_per_game: 0, best_of: 6, time_limit: 4000000000, ball_speed: 1e9, \
+             paddle_move_speed: 0, font_size: inf, music_volume: 500)",
+        );
+        let settings = Settings::load();
+        assert_eq!(settings.points_per_game, 1);
+        assert_eq!(settings.best_of, 7);
+        assert_eq!(settings.time_limit, MAX_TIME_LIMIT);
+        assert_eq!(settings.ball_speed, MAX_BALL_SPEED);
+        assert_eq!(settings.paddle_move_speed, MIN_PADDLE_MOVE_SPEED);
+        assert_eq!(settings.font_size, Settings::default().font_size);
+        assert_eq!(settings.music_volume, 100);
+        // nothing else was in the file
+        assert_eq!(settings.effects_volume, Settings::default().effects_volume);
+
+        let rules = settings.rules();
+        assert_eq!(rules.best_of, 7);
+        assert_eq!(
+            rules.time_limit,
+            Some(MAX_TIME_LIMIT * game::TICK_RATE as u32)
+        );
+    }
+
+    #[test]
+    fn time_limits_are_rounded_to_whole_minutes() {
+        for (seconds, rounded) in [(0, 0), (20, 60), (89, 60), (90, 120), (120, 120)] {
+            let settings = Settings {
+                time_limit: seconds,
+                ..Settings::default()
+            };
+            assert_eq!(
+                settings.in_range().time_limit,
+                rounded,
+                "{} seconds",
+                seconds
+            );
+        }
+    }
+}
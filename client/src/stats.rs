@@ -0,0 +1,52 @@
+// Numbers about the match that the simulation doesn't keep, shown once it's
+// over.
+
+use bevy::prelude::*;
+use game::PaddleSide;
+
+use crate::events::{PaddleHit, PointScored};
+
+#[derive(Resource, Default)]
+pub struct Stats {
+    // paddle hits in the point being played
+    rally: usize,
+    longest_rally: usize,
+    hits: [usize; 2],
+    // who scored the last points, and how many of them in a row
+    streak: Option<(PaddleSide, usize)>,
+    longest_streak: usize,
+}
+
+impl Stats {
+    pub fn summary(&self) -> String {
+        format!(
+            "Longest rally: {}   Most points in a row: {}\nPaddle hits: {} - {}",
+            self.longest_rally, self.longest_streak, self.hits[0], self.hits[1]
+        )
+    }
+}
+
+pub fn reset(mut stats: ResMut<Stats>) {
+    *stats = Stats::default();
+}
+
+pub fn count(
+    mut hits: EventReader<PaddleHit>,
+    mut points: EventReader<PointScored>,
+    mut stats: ResMut<Stats>,
+) {
+    for hit in hits.read() {
+        stats.hits[hit.side.index()] += 1;
+        stats.rally += 1;
+    }
+    stats.longest_rally = stats.longest_rally.max(stats.rally);
+    for point in points.read() {
+        let streak = match stats.streak {
+            Some((side, streak)) if side == point.side => streak + 1,
+            _ => 1,
+        };
+        stats.streak = Some((point.side, streak));
+        stats.longest_streak = stats.longest_streak.max(streak);
+        stats.rally = 0;
+    }
+}
@@ -0,0 +1,49 @@
+// Small text files the client keeps between runs. Natively they sit in the
+// working directory next to `assets/`, in the browser they go to local storage.
+// Tests keep them in memory instead of littering the crate directory.
+
+#[cfg(not(any(target_arch = "wasm32", test)))]
+pub fn read(name: &str) -> Option<String> {
+    std::fs::read_to_string(name).ok()
+}
+
+#[cfg(not(any(target_arch = "wasm32", test)))]
+pub fn write(name: &str, contents: &str) {
+    if let Err(e) = std::fs::write(name, contents) {
+        bevy::log::error!("couldn't save {}: {}", name, e);
+    }
+}
+
+#[cfg(test)]
+static FILES: std::sync::Mutex<std::collections::BTreeMap<String, String>> =
+    std::sync::Mutex::new(std::collections::BTreeMap::new());
+
+#[cfg(test)]
+pub fn read(name: &str) -> Option<String> {
+    FILES.lock().unwrap().get(name).cloned()
+}
+
+#[cfg(test)]
+pub fn write(name: &str, contents: &str) {
+    FILES
+        .lock()
+        .unwrap()
+        .insert(name.to_string(), contents.to_string());
+}
+
+#[cfg(all(target_arch = "wasm32", not(test)))]
+fn local_storage() -> Option<web_sys::Storage> {
+    web_sys::window()?.local_storage().ok()?
+}
+
+#[cfg(all(target_arch = "wasm32", not(test)))]
+pub fn read(name: &str) -> Option<String> {
+    local_storage()?.get_item(name).ok()?
+}
+
+#[cfg(all(target_arch = "wasm32", not(test)))]
+pub fn write(name: &str, contents: &str) {
+    if let Some(storage) = local_storage() {
+        let _ = storage.set_item(name, contents);
+    }
+}
@@ -0,0 +1,198 @@
+//! Computer controlled paddles.
+//!
+//! The AI only looks at the [`Simulation`] like a player would look at the
+//! screen and answers with the input for the next tick, so it plays by the same
+//! rules as a person and stays deterministic for a given seed.
+
+use std::collections::VecDeque;
+
+use glam::Vec2;
+
+use crate::{ARENA_HEIGHT, BALL_RADIUS, Ball, PADDLE_WIDTH, PaddleSide, Rng, Simulation, TICK_DT};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Difficulty {
+    Easy,
+    #[default]
+    Medium,
+    Hard,
+}
+
+impl Difficulty {
+    pub const ALL: [Difficulty; 3] = [Difficulty::Easy, Difficulty::Medium, Difficulty::Hard];
+
+    // how many ticks old the ball the AI reacts to is
+    fn reaction_ticks(self) -> usize {
+        match self {
+            Difficulty::Easy => 18,
+            Difficulty::Medium => 10,
+            Difficulty::Hard => 4,
+        }
+    }
+
+    // fraction of the full paddle speed the AI moves at
+    fn max_speed(self) -> f32 {
+        match self {
+            Difficulty::Easy => 0.55,
+            Difficulty::Medium => 0.8,
+            Difficulty::Hard => 1.,
+        }
+    }
+
+    // the AI aims up to this far off where the ball will arrive, past half the
+    // paddle's height it misses now and then
+    fn max_error(self) -> f32 {
+        match self {
+            Difficulty::Easy => 90.,
+            Difficulty::Medium => 60.,
+            Difficulty::Hard => 25.,
+        }
+    }
+
+    // whether it works out where the ball will arrive, bounces included,
+    // rather than just following it
+    fn predicts(self) -> bool {
+        !matches!(self, Difficulty::Easy)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Ai {
+    side: PaddleSide,
+    difficulty: Difficulty,
+    // the ball over the last few ticks, oldest first
+    seen: VecDeque<Ball>,
+    // the ball was coming this way last time the AI looked
+    incoming: bool,
+    error: f32,
+    rng: Rng,
+}
+
+impl Ai {
+    pub fn new(side: PaddleSide, difficulty: Difficulty, seed: u64) -> Self {
+        Ai {
+            side,
+            difficulty,
+            seen: VecDeque::with_capacity(difficulty.reaction_ticks() + 1),
+            incoming: false,
+            error: 0.,
+            rng: Rng(seed),
+        }
+    }
+
+    pub fn side(&self) -> PaddleSide {
+        self.side
+    }
+
+    pub fn difficulty(&self) -> Difficulty {
+        self.difficulty
+    }
+
+    /// Input for this AI's paddle in the next [`Simulation::step`]. Meant to
+    /// be called exactly once per tick.
+    pub fn input(&mut self, sim: &Simulation) -> f32 {
+        self.seen.push_back(sim.ball);
+        if self.seen.len() > self.difficulty.reaction_ticks() {
+            self.seen.pop_front();
+        }
+        let ball = self.seen[0];
+
+        let facing = self.side.facing();
+        let incoming = sim.serve_ticks == 0 && ball.direction.x * facing.x < 0.;
+        if incoming && !self.incoming {
+            self.error = self.rng.next_signed() * self.difficulty.max_error();
+        }
+        self.incoming = incoming;
+
+        let paddle = sim.paddle(self.side);
+        let target = if !incoming {
+            // wait in the middle, nothing to do until the ball comes back
+            0.
+        } else if self.difficulty.predicts() {
+            let face = paddle.position.x + facing.x * (PADDLE_WIDTH / 2. + BALL_RADIUS);
+            arrival_height(ball.position, ball.direction, face) + self.error
+        } else {
+            ball.position.y + self.error
+        };
+
+        // no faster than it takes to land on the target this tick, so it
+        // settles there instead of jittering around it
+        let max_speed = self.difficulty.max_speed();
+        let step = sim.config.paddle_move_speed * TICK_DT;
+        ((target - paddle.position.y) / step).clamp(-max_speed, max_speed)
+    }
+}
+
+/// Height at which a ball moving from `position` along `direction` reaches
+/// `x`, bouncing off the top and bottom walls on the way.
+pub fn arrival_height(position: Vec2, direction: Vec2, x: f32) -> f32 {
+    if direction.x == 0. {
+        return position.y;
+    }
+    let y = position.y + direction.y * (x - position.x) / direction.x;
+    // bouncing between the walls is the same as going straight through a
+    // mirrored copy of the arena, so fold the straight line back in
+    let limit = ARENA_HEIGHT / 2. - BALL_RADIUS;
+    let folded = (y + limit).rem_euclid(4. * limit);
+    if folded > 2. * limit {
+        3. * limit - folded
+    } else {
+        folded - limit
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BALL_SPEED, Input, PADDLE_HEIGHT, PADDLE_X};
+
+    #[test]
+    fn predicts_arrival_through_wall_bounces() {
+        let limit = ARENA_HEIGHT / 2. - BALL_RADIUS;
+        // straight across
+        assert_eq!(arrival_height(Vec2::ZERO, Vec2::X, 500.), 0.);
+        // 45 degrees up, hits the top wall at x = limit and comes back down
+        let direction = Vec2::ONE.normalize();
+        let y = arrival_height(Vec2::ZERO, direction, limit + 100.);
+        assert!((y - (limit - 100.)).abs() < 1e-3);
+        // off both walls
+        let y = arrival_height(Vec2::ZERO, direction, 3. * limit + 50.);
+        assert!((y - (-limit + 50.)).abs() < 1e-3);
+        // coming from the right, going down
+        let y = arrival_height(Vec2::new(100., 0.), Vec2::new(-1., -1.), 100. - limit - 30.);
+        assert!((y - (-limit + 30.)).abs() < 1e-3);
+    }
+
+    #[test]
+    fn hard_ai_returns_a_bouncing_ball() {
+        for seed in 0..20 {
+            let mut sim = Simulation::new(seed);
+            sim.serve_ticks = 0;
+            let angle: f32 = 0.6;
+            sim.ball.direction = Vec2::new(angle.cos(), angle.sin());
+            let mut ai = Ai::new(PaddleSide::Right, Difficulty::Hard, seed);
+
+            let mut returned = false;
+            for _ in 0..(4. * PADDLE_X / (BALL_SPEED * TICK_DT)) as usize {
+                let mut input = Input::default();
+                input.set(PaddleSide::Right, ai.input(&sim));
+                assert_eq!(sim.step(&input), None, "seed {}", seed);
+                if sim.ball.direction.x < 0. {
+                    returned = true;
+                    break;
+                }
+            }
+            assert!(returned, "seed {}", seed);
+        }
+    }
+
+    #[test]
+    fn waits_in_the_middle_while_the_ball_goes_away() {
+        let mut sim = Simulation::new(0);
+        sim.serve_ticks = 0;
+        sim.ball.direction = Vec2::NEG_X;
+        sim.paddles[PaddleSide::Right.index()].position.y = PADDLE_HEIGHT;
+        let mut ai = Ai::new(PaddleSide::Right, Difficulty::Medium, 0);
+        assert!(ai.input(&sim) < 0.);
+    }
+}
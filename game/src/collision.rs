@@ -0,0 +1,228 @@
+//! Continuous collision for the ball.
+//!
+//! Instead of checking for overlap after moving, the ball's motion over a tick
+//! is swept against each obstacle to find when it first touches it, so no speed
+//! is fast enough to pass through a paddle.
+
+use glam::Vec2;
+
+/// Where along a sweep the ball first touches an obstacle.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Hit {
+    /// Fraction of the motion covered before the contact, in `0.0..=1.0`.
+    pub time: f32,
+    /// Unit normal of the touched surface, pointing towards the ball.
+    pub normal: Vec2,
+}
+
+/// Sweeps a circle from `start` by `motion` against the wall whose surface is
+/// `normal · p = offset`, with the open side of the arena towards `normal`.
+pub fn sweep_circle_wall(
+    start: Vec2,
+    motion: Vec2,
+    radius: f32,
+    normal: Vec2,
+    offset: f32,
+) -> Option<Hit> {
+    let approach = normal.dot(motion);
+    if approach >= 0. {
+        return None;
+    }
+    let distance = normal.dot(start) - offset - radius;
+    let time = (distance / -approach).max(0.);
+    (time <= 1.).then_some(Hit { time, normal })
+}
+
+/// Sweeps a circle from `start` by `motion` against the axis aligned box around
+/// `center`. The box grown by the radius has rounded corners, hitting one of
+/// them gives a slanted normal.
+///
+/// A circle that already overlaps the box only hits it if it is moving further
+/// in, and does so right away, so a paddle moving onto the ball still knocks it
+/// back.
+pub fn sweep_circle_aabb(
+    start: Vec2,
+    motion: Vec2,
+    radius: f32,
+    center: Vec2,
+    half_size: Vec2,
+) -> Option<Hit> {
+    let offset = start - center;
+    let closest = offset.clamp(-half_size, half_size);
+    if offset.distance_squared(closest) < radius * radius {
+        let normal = overlap_normal(offset, closest, half_size, radius);
+        return (normal.dot(motion) < 0.).then_some(Hit { time: 0., normal });
+    }
+
+    let expanded = half_size + Vec2::splat(radius);
+    let (x_enter, x_exit) = slab(offset.x, motion.x, expanded.x)?;
+    let (y_enter, y_exit) = slab(offset.y, motion.y, expanded.y)?;
+    let enter = x_enter.max(y_enter);
+    let exit = x_exit.min(y_exit);
+    if enter > exit || exit < 0. || enter > 1. {
+        return None;
+    }
+
+    // the grown box is only flat along the faces, near a corner the circle
+    // has to reach the corner itself
+    let contact = offset + motion * enter.max(0.);
+    if contact.x.abs() > half_size.x && contact.y.abs() > half_size.y {
+        let corner = half_size * contact.signum();
+        let time = sweep_point_circle(offset - corner, motion, radius)?;
+        let normal = (offset + motion * time - corner) / radius;
+        return Some(Hit { time, normal });
+    }
+
+    if enter < 0. {
+        // started inside the grown box's corner square but outside the
+        // rounded corner, and never got to the flat part
+        return None;
+    }
+    let normal = if x_enter > y_enter {
+        Vec2::new(-motion.x.signum(), 0.)
+    } else {
+        Vec2::new(0., -motion.y.signum())
+    };
+    Some(Hit {
+        time: enter,
+        normal,
+    })
+}
+
+// Times at which a point moving along one axis enters and leaves
+// `-half..=half`, unbounded if it's standing still inside.
+fn slab(start: f32, motion: f32, half: f32) -> Option<(f32, f32)> {
+    if motion == 0. {
+        return (start.abs() <= half).then_some((f32::NEG_INFINITY, f32::INFINITY));
+    }
+    let a = (-half - start) / motion;
+    let b = (half - start) / motion;
+    Some((a.min(b), a.max(b)))
+}
+
+// First time in `0.0..=1.0` a point moving from `start` by `motion` gets
+// within `radius` of the origin.
+fn sweep_point_circle(start: Vec2, motion: Vec2, radius: f32) -> Option<f32> {
+    let a = motion.length_squared();
+    let b = 2. * start.dot(motion);
+    let c = start.length_squared() - radius * radius;
+    let discriminant = b * b - 4. * a * c;
+    if a == 0. || discriminant < 0. {
+        return None;
+    }
+    let time = (-b - discriminant.sqrt()) / (2. * a);
+    (0. ..=1.).contains(&time).then_some(time)
+}
+
+// Direction to push an overlapping circle out of the box the shortest way.
+fn overlap_normal(offset: Vec2, closest: Vec2, half_size: Vec2, radius: f32) -> Vec2 {
+    if offset != closest {
+        return (offset - closest).normalize();
+    }
+    // center inside the box itself
+    let depth = half_size + Vec2::splat(radius) - offset.abs();
+    if depth.x < depth.y {
+        Vec2::new(offset.x.signum(), 0.)
+    } else {
+        Vec2::new(0., offset.y.signum())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RADIUS: f32 = 10.;
+    const HALF_SIZE: Vec2 = Vec2::new(25., 75.);
+
+    fn sweep(start: Vec2, motion: Vec2) -> Option<Hit> {
+        sweep_circle_aabb(start, motion, RADIUS, Vec2::ZERO, HALF_SIZE)
+    }
+
+    fn assert_close(a: Vec2, b: Vec2) {
+        assert!(a.abs_diff_eq(b, 1e-4), "{} != {}", a, b);
+    }
+
+    #[test]
+    fn hits_the_face_it_moves_into() {
+        let hit = sweep(Vec2::new(100., 0.), Vec2::new(-100., 0.)).unwrap();
+        // the face is at 25, the circle touches it when its center is at 35
+        assert!((hit.time - 0.65).abs() < 1e-6);
+        assert_eq!(hit.normal, Vec2::X);
+    }
+
+    #[test]
+    fn does_not_tunnel_through_when_moving_far_past() {
+        // ends up well on the other side, a per tick overlap check would miss it
+        let hit = sweep(Vec2::new(100., 10.), Vec2::new(-1000., 0.)).unwrap();
+        assert!((hit.time - 0.065).abs() < 1e-6);
+        assert_eq!(hit.normal, Vec2::X);
+
+        let hit = sweep(Vec2::new(-100., -30.), Vec2::new(5000., 50.)).unwrap();
+        assert_eq!(hit.normal, -Vec2::X);
+    }
+
+    #[test]
+    fn hits_top_and_bottom_faces() {
+        let hit = sweep(Vec2::new(0., 200.), Vec2::new(0., -200.)).unwrap();
+        assert_eq!(hit.normal, Vec2::Y);
+        let hit = sweep(Vec2::new(5., -200.), Vec2::new(0., 200.)).unwrap();
+        assert_eq!(hit.normal, -Vec2::Y);
+    }
+
+    #[test]
+    fn hits_corners_with_a_slanted_normal() {
+        // heads straight at the corner along the diagonal
+        let corner = HALF_SIZE;
+        let start = corner + Vec2::splat(50.);
+        let hit = sweep(start, Vec2::splat(-100.)).unwrap();
+        assert_close(hit.normal, Vec2::ONE.normalize());
+        let contact = start + Vec2::splat(-100.) * hit.time;
+        assert!((contact.distance(corner) - RADIUS).abs() < 1e-3);
+    }
+
+    #[test]
+    fn misses_when_passing_by_a_corner() {
+        // inside the grown box's corner square but outside the rounded corner
+        assert_eq!(
+            sweep(HALF_SIZE + Vec2::splat(9.), Vec2::new(200., 0.)),
+            None
+        );
+        // ends up in the grown box, but short of the rounded corner
+        assert_eq!(sweep(Vec2::new(40., 90.), Vec2::new(-6., -6.)), None);
+        // just wide of the face
+        assert_eq!(sweep(Vec2::new(36., 200.), Vec2::new(0., -400.)), None);
+    }
+
+    #[test]
+    fn misses_when_moving_away_or_falling_short() {
+        assert_eq!(sweep(Vec2::new(100., 0.), Vec2::new(100., 0.)), None);
+        assert_eq!(sweep(Vec2::new(100., 0.), Vec2::new(-60., 0.)), None);
+    }
+
+    #[test]
+    fn overlapping_circles_only_hit_when_moving_in() {
+        let hit = sweep(Vec2::new(30., 0.), Vec2::new(-10., 0.)).unwrap();
+        assert_eq!(
+            hit,
+            Hit {
+                time: 0.,
+                normal: Vec2::X
+            }
+        );
+        assert_eq!(sweep(Vec2::new(30., 0.), Vec2::new(10., 0.)), None);
+    }
+
+    #[test]
+    fn walls_stop_the_circle_at_its_radius() {
+        let hit =
+            sweep_circle_wall(Vec2::ZERO, Vec2::new(10., 100.), RADIUS, -Vec2::Y, -50.).unwrap();
+        // the center stops at y = 40
+        assert!((hit.time - 0.4).abs() < 1e-6);
+        assert_eq!(hit.normal, -Vec2::Y);
+        assert_eq!(
+            sweep_circle_wall(Vec2::ZERO, Vec2::new(0., -100.), RADIUS, -Vec2::Y, -50.),
+            None
+        );
+    }
+}
@@ -0,0 +1,734 @@
+//! Headless pong simulation shared by the client and the server.
+//!
+//! Nothing in here knows about bevy or windows: the arena has a fixed size and
+//! everything advances through [`Simulation::step`], so two machines fed the
+//! same inputs end up in the same state. Trigonometry goes through `libm`
+//! rather than the platform's own, which may round differently.
+
+use std::fmt;
+
+pub use glam::Vec2;
+
+pub mod ai;
+pub mod collision;
+pub mod replay;
+pub mod rules;
+use collision::{Hit, sweep_circle_aabb, sweep_circle_wall};
+use rules::Rules;
+
+pub const ARENA_WIDTH: f32 = 1280.;
+pub const ARENA_HEIGHT: f32 = 720.;
+
+// the simulation always advances in steps of TICK_DT, speeds are in units per second
+pub const TICK_RATE: f32 = 60.;
+pub const TICK_DT: f32 = 1. / TICK_RATE;
+
+// defaults for the tunable parts of a match, see `Config`
+pub const PADDLE_MOVE_SPEED: f32 = 900.;
+pub const PADDLE_WIDTH: f32 = 50.;
+pub const PADDLE_HEIGHT: f32 = 150.;
+pub const PADDLE_X: f32 = 600.;
+pub const BALL_RADIUS: f32 = 15.;
+pub const BALL_SPEED: f32 = 1800.;
+// every paddle hit speeds the ball up, until it reaches the cap
+pub const BALL_SPEED_UP: f32 = 120.;
+pub const MAX_BALL_SPEED: f32 = 3600.;
+// angle off the horizontal the ball leaves a paddle at when it hits the very
+// end of the paddle's face, it goes straight back when it hits the middle
+pub const MAX_BOUNCE_ANGLE: f32 = std::f32::consts::PI / 3.;
+// extra angle put on the ball by a paddle moving at full speed
+pub const SPIN_ANGLE: f32 = std::f32::consts::PI / 12.;
+pub const POINTS_TO_WIN: usize = 3;
+
+// points start with a countdown of this many beats before the serve
+pub const SERVE_COUNTDOWN: u32 = 3;
+pub const SERVE_BEAT_TICKS: u32 = 20;
+// serves leave at most this far off the horizontal
+pub const MAX_SERVE_ANGLE: f32 = std::f32::consts::PI / 6.;
+
+// a ball wedged between a paddle and a wall could otherwise bounce forever
+const MAX_BOUNCES_PER_TICK: usize = 4;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PaddleSide {
+    Left,
+    Right,
+}
+
+impl PaddleSide {
+    pub const ALL: [PaddleSide; 2] = [PaddleSide::Left, PaddleSide::Right];
+
+    /// Position of this side in per-side arrays like [`Simulation::paddles`].
+    pub fn index(self) -> usize {
+        match self {
+            PaddleSide::Left => 0,
+            PaddleSide::Right => 1,
+        }
+    }
+
+    /// The way this side's paddle faces, towards the other side.
+    pub fn facing(self) -> Vec2 {
+        match self {
+            PaddleSide::Left => Vec2::X,
+            PaddleSide::Right => Vec2::NEG_X,
+        }
+    }
+
+    pub fn opposite(self) -> PaddleSide {
+        match self {
+            PaddleSide::Left => PaddleSide::Right,
+            PaddleSide::Right => PaddleSide::Left,
+        }
+    }
+}
+
+impl fmt::Display for PaddleSide {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PaddleSide::Left => write!(f, "left"),
+            PaddleSide::Right => write!(f, "right"),
+        }
+    }
+}
+
+/// Vertical movement requested for each paddle, in `-1.0..=1.0`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Input {
+    pub left: f32,
+    pub right: f32,
+}
+
+impl Input {
+    pub fn get(&self, side: PaddleSide) -> f32 {
+        match side {
+            PaddleSide::Left => self.left,
+            PaddleSide::Right => self.right,
+        }
+    }
+
+    pub fn set(&mut self, side: PaddleSide, value: f32) {
+        match side {
+            PaddleSide::Left => self.left = value,
+            PaddleSide::Right => self.right = value,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Score {
+    pub left: usize,
+    pub right: usize,
+}
+
+impl Score {
+    pub fn get(&self, side: PaddleSide) -> usize {
+        match side {
+            PaddleSide::Left => self.left,
+            PaddleSide::Right => self.right,
+        }
+    }
+
+    /// The side with more points, if either.
+    pub fn leader(&self) -> Option<PaddleSide> {
+        match self.left.cmp(&self.right) {
+            std::cmp::Ordering::Greater => Some(PaddleSide::Left),
+            std::cmp::Ordering::Less => Some(PaddleSide::Right),
+            std::cmp::Ordering::Equal => None,
+        }
+    }
+
+    pub(crate) fn add_point(&mut self, side: PaddleSide) {
+        match side {
+            PaddleSide::Left => self.left += 1,
+            PaddleSide::Right => self.right += 1,
+        }
+    }
+}
+
+/// The parts of a match that can be tuned. Online matches use the defaults,
+/// apart from the rules which the server shares with both players.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Config {
+    pub rules: Rules,
+    /// Speed balls are served at, in units per second.
+    pub ball_speed: f32,
+    pub paddle_move_speed: f32,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            rules: Rules::default(),
+            ball_speed: BALL_SPEED,
+            paddle_move_speed: PADDLE_MOVE_SPEED,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Paddle {
+    pub side: PaddleSide,
+    pub position: Vec2,
+    /// Vertical speed over the last tick, puts spin on the ball.
+    pub velocity: f32,
+}
+
+impl Paddle {
+    fn new(side: PaddleSide) -> Self {
+        let x = match side {
+            PaddleSide::Left => -PADDLE_X,
+            PaddleSide::Right => PADDLE_X,
+        };
+        Paddle {
+            side,
+            position: Vec2::new(x, 0.),
+            velocity: 0.,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Ball {
+    pub position: Vec2,
+    /// Always of unit length.
+    pub direction: Vec2,
+    /// In units per second.
+    pub speed: f32,
+}
+
+impl Ball {
+    fn new(direction: Vec2, speed: f32) -> Self {
+        Ball {
+            position: Vec2::ZERO,
+            direction,
+            speed,
+        }
+    }
+}
+
+// Small deterministic generator (SplitMix64). Written out here rather than
+// pulled in so the same seed is guaranteed to give the same serves on the
+// server, every client and any later version.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Rng(pub(crate) u64);
+
+impl Rng {
+    pub(crate) fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    // uniform in -1.0..1.0
+    pub(crate) fn next_signed(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1 << 23) as f32 - 1.
+    }
+}
+
+/// Something that happened during a tick, see [`Simulation::events`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Event {
+    /// The ball bounced off a paddle, its face or anywhere else.
+    PaddleHit(PaddleSide),
+    WallHit,
+    PointScored(PaddleSide),
+    GameWon(PaddleSide),
+    MatchWon(PaddleSide),
+}
+
+// what the ball ran into
+#[derive(Clone, Copy)]
+enum Obstacle {
+    Wall,
+    Paddle(PaddleSide),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Simulation {
+    pub paddles: [Paddle; 2],
+    pub ball: Ball,
+    /// Points in the game being played.
+    pub score: Score,
+    /// Final score of each game played so far.
+    pub games: Vec<Score>,
+    /// Ticks the ball has been in play for, what time limits count.
+    pub clock_ticks: u32,
+    /// Ticks left before the ball is served, it stays put until then.
+    pub serve_ticks: u32,
+    pub config: Config,
+    /// What happened during the last [`Simulation::step`], in order.
+    pub events: Vec<Event>,
+    winner: Option<PaddleSide>,
+    rng: Rng,
+}
+
+impl Simulation {
+    /// Starts a match with the default [`Config`]. Matches started with the
+    /// same seed and fed the same inputs play out exactly the same.
+    pub fn new(seed: u64) -> Self {
+        Simulation::with_config(seed, Config::default())
+    }
+
+    pub fn with_config(seed: u64, config: Config) -> Self {
+        let mut sim = Simulation {
+            paddles: PaddleSide::ALL.map(Paddle::new),
+            ball: Ball::new(Vec2::ZERO, config.ball_speed),
+            score: Score::default(),
+            games: Vec::new(),
+            clock_ticks: 0,
+            serve_ticks: 0,
+            config,
+            events: Vec::new(),
+            winner: None,
+            rng: Rng(seed),
+        };
+        let receiver = PaddleSide::ALL[(sim.rng.next_u64() % 2) as usize];
+        sim.serve(receiver);
+        sim
+    }
+
+    pub fn paddle(&self, side: PaddleSide) -> &Paddle {
+        &self.paddles[side.index()]
+    }
+
+    /// The side that won the match, once it's over.
+    pub fn winner(&self) -> Option<PaddleSide> {
+        self.winner
+    }
+
+    /// Ticks of play left under a time limit.
+    pub fn time_left(&self) -> Option<u32> {
+        let limit = self.config.rules.time_limit?;
+        Some(limit.saturating_sub(self.clock_ticks))
+    }
+
+    /// Out of time with the match level, the next point wins it.
+    pub fn sudden_death(&self) -> bool {
+        self.time_left() == Some(0) && self.winner.is_none()
+    }
+
+    /// What the serve countdown shows, `None` once the ball is in play.
+    pub fn serve_countdown(&self) -> Option<u32> {
+        (self.serve_ticks > 0).then(|| self.serve_ticks.div_ceil(SERVE_BEAT_TICKS))
+    }
+
+    /// Puts the paddles and the ball back where they start a point, with the
+    /// ball served at a random angle towards `receiver` after the countdown.
+    pub fn serve(&mut self, receiver: PaddleSide) {
+        let angle = self.rng.next_signed() * MAX_SERVE_ANGLE;
+        let towards = -receiver.facing().x;
+
+        self.paddles = PaddleSide::ALL.map(Paddle::new);
+        self.ball = Ball::new(
+            Vec2::new(towards * libm::cosf(angle), libm::sinf(angle)),
+            self.config.ball_speed,
+        );
+        self.serve_ticks = SERVE_COUNTDOWN * SERVE_BEAT_TICKS;
+    }
+
+    /// Advances the game by one tick of [`TICK_DT`]. Returns the side that scored, if any,
+    /// in which case the next point is already being served to the other side.
+    pub fn step(&mut self, input: &Input) -> Option<PaddleSide> {
+        self.events.clear();
+        self.move_paddles(input);
+
+        if self.serve_ticks > 0 {
+            self.serve_ticks -= 1;
+            return None;
+        }
+
+        self.clock_ticks += 1;
+        if let Some(scorer) = self.move_ball() {
+            self.score_point(scorer);
+            self.serve(scorer.opposite());
+            return Some(scorer);
+        }
+
+        None
+    }
+
+    fn score_point(&mut self, scorer: PaddleSide) {
+        let rules = self.config.rules;
+        self.score.add_point(scorer);
+        self.events.push(Event::PointScored(scorer));
+        if let Some(winner) = rules.game_winner(self.score) {
+            self.games.push(self.score);
+            self.score = Score::default();
+            self.events.push(Event::GameWon(winner));
+        }
+        let time_up = self.time_left() == Some(0);
+        self.winner = rules.match_winner(&self.games, self.score, time_up);
+        if let Some(winner) = self.winner {
+            self.events.push(Event::MatchWon(winner));
+        }
+    }
+
+    /// Moves one paddle as a tick of [`Simulation::step`] would, without touching the
+    /// rest of the game. Lets online clients predict their own paddle.
+    pub fn move_paddle(&mut self, side: PaddleSide, direction: f32) {
+        // input comes from the network too, NaN would stick to the paddle
+        let direction = if direction.is_finite() { direction } else { 0. };
+        let limit = ARENA_HEIGHT / 2. - PADDLE_HEIGHT / 2.;
+        let speed = self.config.paddle_move_speed;
+        let paddle = &mut self.paddles[side.index()];
+        let y =
+            (paddle.position.y + speed * TICK_DT * direction.clamp(-1., 1.)).clamp(-limit, limit);
+        paddle.velocity = (y - paddle.position.y) / TICK_DT;
+        paddle.position.y = y;
+    }
+
+    fn move_paddles(&mut self, input: &Input) {
+        for side in PaddleSide::ALL {
+            self.move_paddle(side, input.get(side));
+        }
+    }
+
+    // Moves the ball a tick's worth of distance, bouncing off whatever it
+    // runs into on the way, then checks whether it reached either goal.
+    fn move_ball(&mut self) -> Option<PaddleSide> {
+        // fraction of the tick the ball still has to travel for
+        let mut remaining = 1.;
+        for _ in 0..MAX_BOUNCES_PER_TICK {
+            let motion = self.ball.direction * self.ball.speed * TICK_DT * remaining;
+            let Some((hit, obstacle)) = self.first_hit(motion) else {
+                self.ball.position += motion;
+                break;
+            };
+            self.ball.position += motion * hit.time;
+            remaining *= 1. - hit.time;
+            self.events.push(match obstacle {
+                Obstacle::Wall => Event::WallHit,
+                Obstacle::Paddle(side) => Event::PaddleHit(side),
+            });
+            match obstacle {
+                Obstacle::Paddle(side) if hit.normal == side.facing() => self.return_ball(side),
+                // walls, and the back, ends and corners of paddles
+                _ => self.ball.direction = self.ball.direction.reflect(hit.normal),
+            }
+        }
+
+        let ball = &self.ball;
+        if ball.position.x + BALL_RADIUS >= ARENA_WIDTH / 2. {
+            return Some(PaddleSide::Left);
+        }
+        if ball.position.x - BALL_RADIUS <= -ARENA_WIDTH / 2. {
+            return Some(PaddleSide::Right);
+        }
+        None
+    }
+
+    // Sends the ball back off the face of a paddle. Where it hits the face
+    // picks the angle, how fast the paddle is moving adds some spin on top.
+    fn return_ball(&mut self, side: PaddleSide) {
+        let paddle = *self.paddle(side);
+        let ball = &mut self.ball;
+
+        let reach = PADDLE_HEIGHT / 2. + BALL_RADIUS;
+        let offset = ((ball.position.y - paddle.position.y) / reach).clamp(-1., 1.);
+        let spin = paddle.velocity / self.config.paddle_move_speed * SPIN_ANGLE;
+        let angle = (offset * MAX_BOUNCE_ANGLE + spin).clamp(-MAX_BOUNCE_ANGLE, MAX_BOUNCE_ANGLE);
+
+        ball.direction = Vec2::new(side.facing().x * libm::cosf(angle), libm::sinf(angle));
+        // a ball served faster than the cap just doesn't speed up
+        ball.speed = (ball.speed + BALL_SPEED_UP)
+            .min(MAX_BALL_SPEED)
+            .max(ball.speed);
+    }
+
+    fn first_hit(&self, motion: Vec2) -> Option<(Hit, Obstacle)> {
+        let position = self.ball.position;
+        let walls = [
+            (Vec2::NEG_Y, -ARENA_HEIGHT / 2.),
+            (Vec2::Y, -ARENA_HEIGHT / 2.),
+        ]
+        .map(|(normal, offset)| {
+            sweep_circle_wall(position, motion, BALL_RADIUS, normal, offset)
+                .map(|hit| (hit, Obstacle::Wall))
+        });
+        let paddles = self.paddles.map(|paddle| {
+            sweep_circle_aabb(
+                position,
+                motion,
+                BALL_RADIUS,
+                paddle.position,
+                Vec2::new(PADDLE_WIDTH, PADDLE_HEIGHT) / 2.,
+            )
+            .map(|hit| (hit, Obstacle::Paddle(paddle.side)))
+        });
+
+        walls
+            .into_iter()
+            .chain(paddles)
+            .flatten()
+            .min_by(|(a, _), (b, _)| a.time.total_cmp(&b.time))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a match where the ball is already in play
+    fn in_play() -> Simulation {
+        let mut sim = Simulation::new(0);
+        sim.serve_ticks = 0;
+        sim
+    }
+
+    const TICK_DISTANCE: f32 = BALL_SPEED * TICK_DT;
+
+    #[test]
+    fn ball_bounces_off_a_paddle_within_a_tick() {
+        let mut sim = in_play();
+        let face = -PADDLE_X + PADDLE_WIDTH / 2.;
+        // reaches the paddle a third of the way through the tick
+        sim.ball.position = Vec2::new(face + BALL_RADIUS + TICK_DISTANCE / 3., 0.);
+        sim.ball.direction = Vec2::NEG_X;
+
+        assert_eq!(sim.step(&Input::default()), None);
+        // dead center, straight back and a little faster
+        assert_eq!(sim.ball.direction, Vec2::X);
+        assert_eq!(sim.ball.speed, BALL_SPEED + BALL_SPEED_UP);
+        let expected = face + BALL_RADIUS + (BALL_SPEED + BALL_SPEED_UP) * TICK_DT * 2. / 3.;
+        assert!((sim.ball.position.x - expected).abs() < 1e-3);
+        assert_eq!(sim.events, [Event::PaddleHit(PaddleSide::Left)]);
+
+        sim.step(&Input::default());
+        assert_eq!(sim.events, []);
+    }
+
+    // sets the ball up to hit the right paddle's face this tick, `offset`
+    // above its center
+    fn about_to_hit_right_paddle(offset: f32) -> Simulation {
+        let mut sim = in_play();
+        let face = PADDLE_X - PADDLE_WIDTH / 2.;
+        sim.ball.position = Vec2::new(face - BALL_RADIUS - 1., offset);
+        sim.ball.direction = Vec2::X;
+        sim
+    }
+
+    #[test]
+    fn bounce_angle_depends_on_where_the_paddle_is_hit() {
+        let mut sim = about_to_hit_right_paddle(PADDLE_HEIGHT / 4.);
+        sim.step(&Input::default());
+        let up = sim.ball.direction;
+        assert!(up.x < 0. && up.y > 0.);
+
+        let mut sim = about_to_hit_right_paddle(PADDLE_HEIGHT / 2.);
+        sim.step(&Input::default());
+        let steeper = sim.ball.direction;
+        assert!(steeper.y > up.y);
+        assert!(steeper.y.atan2(-steeper.x) <= MAX_BOUNCE_ANGLE + 1e-6);
+
+        let mut sim = about_to_hit_right_paddle(-PADDLE_HEIGHT / 4.);
+        sim.step(&Input::default());
+        assert!((sim.ball.direction.y + up.y).abs() < 1e-6);
+    }
+
+    #[test]
+    fn moving_paddles_put_spin_on_the_ball() {
+        let mut still = about_to_hit_right_paddle(0.);
+        still.step(&Input::default());
+
+        let mut moving = about_to_hit_right_paddle(0.);
+        // the paddle is still level with the ball after moving up this tick
+        moving.ball.position.y = PADDLE_MOVE_SPEED * TICK_DT;
+        moving.step(&Input {
+            left: 0.,
+            right: 1.,
+        });
+
+        assert_eq!(still.ball.direction, Vec2::NEG_X);
+        assert!(moving.ball.direction.y > 0.);
+    }
+
+    #[test]
+    fn ball_speed_is_capped() {
+        let mut sim = about_to_hit_right_paddle(0.);
+        sim.ball.speed = MAX_BALL_SPEED - 1.;
+        sim.step(&Input::default());
+        assert_eq!(sim.ball.speed, MAX_BALL_SPEED);
+    }
+
+    #[test]
+    fn paddles_move_with_their_input_and_stop_at_the_walls() {
+        let mut sim = Simulation::new(0);
+        let input = Input {
+            left: 1.,
+            right: -0.5,
+        };
+        sim.step(&input);
+        let step = PADDLE_MOVE_SPEED * TICK_DT;
+        assert_eq!(sim.paddle(PaddleSide::Left).position.y, step);
+        assert_eq!(sim.paddle(PaddleSide::Right).position.y, -step / 2.);
+        assert_eq!(sim.paddle(PaddleSide::Left).velocity, PADDLE_MOVE_SPEED);
+
+        for _ in 0..(ARENA_HEIGHT / step) as usize {
+            sim.step(&input);
+        }
+        let limit = ARENA_HEIGHT / 2. - PADDLE_HEIGHT / 2.;
+        assert_eq!(sim.paddle(PaddleSide::Left).position.y, limit);
+        assert_eq!(sim.paddle(PaddleSide::Left).velocity, 0.);
+        assert_eq!(sim.paddle(PaddleSide::Right).position.y, -limit);
+    }
+
+    #[test]
+    fn paddles_ignore_input_that_is_not_a_number() {
+        let mut sim = Simulation::new(0);
+        for direction in [f32::NAN, f32::INFINITY, f32::NEG_INFINITY] {
+            sim.move_paddle(PaddleSide::Left, direction);
+            assert_eq!(sim.paddle(PaddleSide::Left).position.y, 0.);
+            assert_eq!(sim.paddle(PaddleSide::Left).velocity, 0.);
+        }
+    }
+
+    #[test]
+    fn ball_bounces_off_walls_at_the_same_angle() {
+        let mut sim = in_play();
+        sim.ball.position = Vec2::new(0., ARENA_HEIGHT / 2. - BALL_RADIUS - 1.);
+        sim.ball.direction = Vec2::new(1., 1.).normalize();
+
+        sim.step(&Input::default());
+        assert!(
+            sim.ball
+                .direction
+                .abs_diff_eq(Vec2::new(1., -1.).normalize(), 1e-6)
+        );
+        assert!(sim.ball.position.y + BALL_RADIUS < ARENA_HEIGHT / 2.);
+    }
+
+    #[test]
+    fn ball_past_a_paddle_scores() {
+        let mut sim = in_play();
+        sim.ball.position = Vec2::new(-ARENA_WIDTH / 2. + BALL_RADIUS + 1., 300.);
+        sim.ball.direction = Vec2::NEG_X;
+
+        assert_eq!(sim.step(&Input::default()), Some(PaddleSide::Right));
+        assert_eq!(sim.score.right, 1);
+        assert_eq!(sim.ball.position, Vec2::ZERO);
+    }
+
+    #[test]
+    fn serves_go_to_whoever_lost_the_point_after_a_countdown() {
+        let mut sim = in_play();
+        sim.ball.position = Vec2::new(ARENA_WIDTH / 2. - BALL_RADIUS - 1., 0.);
+        sim.ball.direction = Vec2::X;
+        assert_eq!(sim.step(&Input::default()), Some(PaddleSide::Left));
+
+        assert_eq!(sim.serve_countdown(), Some(SERVE_COUNTDOWN));
+        assert!(sim.ball.direction.x > 0.);
+        let angle = sim.ball.direction.y.atan2(sim.ball.direction.x);
+        assert!(angle.abs() <= MAX_SERVE_ANGLE);
+
+        for _ in 0..SERVE_COUNTDOWN * SERVE_BEAT_TICKS {
+            sim.step(&Input::default());
+            assert_eq!(sim.ball.position, Vec2::ZERO);
+        }
+        assert_eq!(sim.serve_countdown(), None);
+        sim.step(&Input::default());
+        assert!(sim.ball.position.x > 0.);
+    }
+
+    #[test]
+    fn the_same_seed_serves_the_same_way() {
+        let serves = |seed| {
+            let mut sim = Simulation::new(seed);
+            let mut directions = vec![sim.ball.direction];
+            for receiver in [PaddleSide::Left, PaddleSide::Right, PaddleSide::Left] {
+                sim.serve(receiver);
+                directions.push(sim.ball.direction);
+            }
+            directions
+        };
+
+        assert_eq!(serves(42), serves(42));
+        assert_ne!(serves(42), serves(43));
+    }
+
+    #[test]
+    fn config_sets_the_serve_speed_and_match_length() {
+        let config = Config {
+            rules: Rules {
+                points_per_game: 1,
+                ..Rules::default()
+            },
+            ball_speed: 1000.,
+            ..Config::default()
+        };
+        let mut sim = Simulation::with_config(0, config);
+        assert_eq!(sim.ball.speed, 1000.);
+        assert_eq!(sim.winner(), None);
+
+        about_to_score_left(&mut sim);
+        assert_eq!(sim.step(&Input::default()), Some(PaddleSide::Left));
+        assert_eq!(sim.winner(), Some(PaddleSide::Left));
+        assert_eq!(sim.ball.speed, 1000.);
+    }
+
+    // puts the ball just short of the right goal, so stepping scores for the left
+    fn about_to_score_left(sim: &mut Simulation) {
+        sim.serve_ticks = 0;
+        sim.ball.position = Vec2::new(ARENA_WIDTH / 2., 0.);
+        sim.ball.direction = Vec2::X;
+    }
+
+    #[test]
+    fn games_are_recorded_as_they_are_won() {
+        let config = Config {
+            rules: Rules {
+                points_per_game: 2,
+                best_of: 3,
+                ..Rules::default()
+            },
+            ..Config::default()
+        };
+        let mut sim = Simulation::with_config(0, config);
+        for _ in 0..3 {
+            about_to_score_left(&mut sim);
+            sim.step(&Input::default());
+        }
+        assert_eq!(sim.games, vec![Score { left: 2, right: 0 }]);
+        assert_eq!(sim.score, Score { left: 1, right: 0 });
+        assert_eq!(sim.winner(), None);
+
+        about_to_score_left(&mut sim);
+        sim.step(&Input::default());
+        assert_eq!(sim.games.len(), 2);
+        assert_eq!(sim.winner(), Some(PaddleSide::Left));
+        assert_eq!(
+            sim.events,
+            [
+                Event::PointScored(PaddleSide::Left),
+                Event::GameWon(PaddleSide::Left),
+                Event::MatchWon(PaddleSide::Left),
+            ]
+        );
+    }
+
+    #[test]
+    fn the_clock_only_runs_while_the_ball_is_in_play() {
+        let config = Config {
+            rules: Rules {
+                time_limit: Some(2),
+                ..Rules::default()
+            },
+            ..Config::default()
+        };
+        let mut sim = Simulation::with_config(0, config);
+        sim.step(&Input::default());
+        assert_eq!(sim.time_left(), Some(2));
+
+        sim.serve_ticks = 0;
+        sim.step(&Input::default());
+        sim.step(&Input::default());
+        assert_eq!(sim.time_left(), Some(0));
+        assert!(sim.sudden_death());
+
+        about_to_score_left(&mut sim);
+        sim.step(&Input::default());
+        assert_eq!(sim.winner(), Some(PaddleSide::Left));
+        assert!(!sim.sudden_death());
+    }
+}
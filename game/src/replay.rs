@@ -0,0 +1,292 @@
+//! Recordings of whole matches.
+//!
+//! A match is fully determined by its seed, its [`Config`] and the input of
+//! every tick, so that's all a replay stores. Playing it back is just stepping
+//! a fresh [`Simulation`] through the same inputs.
+//!
+//! The file format is plain text so it fits anywhere a string does:
+//!
+//! ```text
+//! pong-replay 1
+//! seed 1234
+//! rules 3 0 1 0
+//! speeds 1800 900
+//! 120 0 0
+//! 14 1 -1
+//! ```
+//!
+//! The rules line holds points per game, win by two, best of and the time
+//! limit in ticks (0 for none), then come runs of identical inputs as a count
+//! followed by the left and right paddle's input.
+
+use std::fmt;
+
+use crate::{Config, Input, Simulation, TICK_RATE, rules::Rules};
+
+const HEADER: &str = "pong-replay 1";
+
+/// Longest replay there is, four hours of play. Recording stops here and
+/// longer files are refused rather than filling up memory.
+pub const MAX_TICKS: usize = 4 * 60 * 60 * TICK_RATE as usize;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Replay {
+    pub seed: u64,
+    pub config: Config,
+    /// One per tick the match was stepped.
+    pub inputs: Vec<Input>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct ParseError {
+    /// 1 based, like editors count them.
+    pub line: usize,
+    pub reason: &'static str,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.reason)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl Replay {
+    /// Starts recording a match created with [`Simulation::with_config`].
+    pub fn new(seed: u64, config: Config) -> Self {
+        Replay {
+            seed,
+            config,
+            inputs: Vec::new(),
+        }
+    }
+
+    /// Call with the input of every [`Simulation::step`], in order.
+    pub fn record(&mut self, input: Input) {
+        if self.inputs.len() < MAX_TICKS {
+            self.inputs.push(input);
+        }
+    }
+
+    /// The match as it was after its first `ticks` ticks.
+    pub fn simulate(&self, ticks: usize) -> Simulation {
+        let mut sim = Simulation::with_config(self.seed, self.config);
+        for input in self.inputs.iter().take(ticks) {
+            sim.step(input);
+        }
+        sim
+    }
+
+    pub fn encode(&self) -> String {
+        let rules = self.config.rules;
+        let mut text = format!(
+            "{}\nseed {}\nrules {} {} {} {}\nspeeds {} {}\n",
+            HEADER,
+            self.seed,
+            rules.points_per_game,
+            u8::from(rules.win_by_two),
+            rules.best_of,
+            rules.time_limit.unwrap_or(0),
+            self.config.ball_speed,
+            self.config.paddle_move_speed,
+        );
+        // floats print as the shortest text that reads back the same, so
+        // playback sees exactly the recorded inputs
+        let mut inputs = self.inputs.iter().peekable();
+        while let Some(input) = inputs.next() {
+            let mut count = 1;
+            while inputs.next_if_eq(&input).is_some() {
+                count += 1;
+            }
+            text += &format!("{} {} {}\n", count, input.left, input.right);
+        }
+        text
+    }
+
+    pub fn decode(text: &str) -> Result<Replay, ParseError> {
+        let mut lines = text.lines().enumerate().map(|(i, line)| (i + 1, line));
+        let mut next_line = |reason| lines.next().ok_or(ParseError { line: 0, reason });
+
+        let (line, header) = next_line("empty file")?;
+        if header.trim() != HEADER {
+            return Err(ParseError {
+                line,
+                reason: "not a replay, or from a newer version",
+            });
+        }
+        let (line, seed) = next_line("missing seed")?;
+        let seed = fields(line, seed, "seed", 1)?;
+        let seed = parse(line, seed[0])?;
+        let (line, rules) = next_line("missing rules")?;
+        let rules = fields(line, rules, "rules", 4)?;
+        let win_by_two: u8 = parse(line, rules[1])?;
+        let time_limit = parse(line, rules[3])?;
+        let rules = Rules {
+            points_per_game: parse(line, rules[0])?,
+            win_by_two: win_by_two != 0,
+            best_of: parse(line, rules[2])?,
+            time_limit: (time_limit > 0).then_some(time_limit),
+        };
+        rules
+            .validate()
+            .map_err(|reason| ParseError { line, reason })?;
+        let (line, speeds) = next_line("missing speeds")?;
+        let speeds = fields(line, speeds, "speeds", 2)?;
+        let ball_speed: f32 = parse(line, speeds[0])?;
+        let paddle_move_speed: f32 = parse(line, speeds[1])?;
+        let speed = |speed: f32| speed.is_finite() && speed > 0.;
+        if !(speed(ball_speed) && speed(paddle_move_speed)) {
+            return Err(ParseError {
+                line,
+                reason: "invalid speeds",
+            });
+        }
+
+        let mut replay = Replay::new(
+            seed,
+            Config {
+                rules,
+                ball_speed,
+                paddle_move_speed,
+            },
+        );
+        for (line, run) in lines {
+            if run.trim().is_empty() {
+                continue;
+            }
+            let parts: Vec<_> = run.split_whitespace().collect();
+            let [count, left, right] = parts[..] else {
+                return Err(ParseError {
+                    line,
+                    reason: "wrong number of fields",
+                });
+            };
+            let count: usize = parse(line, count)?;
+            let input = Input {
+                left: parse(line, left)?,
+                right: parse(line, right)?,
+            };
+            if !(input.left.is_finite() && input.right.is_finite()) {
+                return Err(ParseError {
+                    line,
+                    reason: "invalid input",
+                });
+            }
+            if count > MAX_TICKS - replay.inputs.len() {
+                return Err(ParseError {
+                    line,
+                    reason: "replay is too long",
+                });
+            }
+            replay.inputs.extend(std::iter::repeat_n(input, count));
+        }
+        Ok(replay)
+    }
+}
+
+// the `count` values after a line's keyword
+fn fields<'a>(
+    line: usize,
+    text: &'a str,
+    keyword: &str,
+    count: usize,
+) -> Result<Vec<&'a str>, ParseError> {
+    let mut parts = text.split_whitespace();
+    if parts.next() != Some(keyword) {
+        return Err(ParseError {
+            line,
+            reason: "unexpected line",
+        });
+    }
+    let values: Vec<_> = parts.collect();
+    if values.len() != count {
+        return Err(ParseError {
+            line,
+            reason: "wrong number of fields",
+        });
+    }
+    Ok(values)
+}
+
+fn parse<T: std::str::FromStr>(line: usize, part: &str) -> Result<T, ParseError> {
+    part.parse().map_err(|_| ParseError {
+        line,
+        reason: "invalid number",
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn playback_matches_the_recorded_match() {
+        let config = Config {
+            rules: Rules {
+                best_of: 3,
+                ..Rules::default()
+            },
+            ..Config::default()
+        };
+        let mut sim = Simulation::with_config(7, config);
+        let mut replay = Replay::new(7, config);
+        // paddles that wander up and down, slowly so the inputs repeat
+        for tick in 0..20_000 {
+            let input = Input {
+                left: ((tick / 40) % 3) as f32 - 1.,
+                right: (tick as f32 / 97.).sin(),
+            };
+            sim.step(&input);
+            replay.record(input);
+        }
+
+        let replay = Replay::decode(&replay.encode()).unwrap();
+        let played_back = replay.simulate(replay.inputs.len());
+        assert!(!sim.games.is_empty());
+        assert_eq!(played_back, sim);
+    }
+
+    #[test]
+    fn rejects_broken_files() {
+        let replay = Replay::new(1, Config::default()).encode();
+        assert!(Replay::decode(&replay).is_ok());
+        assert_eq!(Replay::decode("").unwrap_err().reason, "empty file");
+        let bad_run = format!("{}3 0\n", replay);
+        assert_eq!(Replay::decode(&bad_run).unwrap_err().line, 5);
+        let bad_rules = replay.replace("rules 3 0 1", "rules 3 0 2");
+        let even = Rules {
+            best_of: 2,
+            ..Rules::default()
+        };
+        assert_eq!(
+            Replay::decode(&bad_rules).unwrap_err().reason,
+            even.validate().unwrap_err()
+        );
+        let not_a_number = format!("{}3 NaN 0\n", replay);
+        assert_eq!(
+            Replay::decode(&not_a_number).unwrap_err().reason,
+            "invalid input"
+        );
+        let no_speed = replay.replace("speeds 1800", "speeds inf");
+        assert_eq!(
+            Replay::decode(&no_speed).unwrap_err().reason,
+            "invalid speeds"
+        );
+    }
+
+    #[test]
+    fn refuses_to_play_for_longer_than_the_cap() {
+        let replay = Replay::new(1, Config::default()).encode();
+        let longest = format!("{}{} 0 0\n", replay, MAX_TICKS);
+        assert_eq!(Replay::decode(&longest).unwrap().inputs.len(), MAX_TICKS);
+
+        let one_more = format!("{}1 1 1\n", longest);
+        assert_eq!(Replay::decode(&one_more).unwrap_err().line, 6);
+        let huge = format!("{}{} 0 0\n", replay, usize::MAX);
+        assert_eq!(
+            Replay::decode(&huge).unwrap_err().reason,
+            "replay is too long"
+        );
+    }
+}
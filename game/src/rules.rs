@@ -0,0 +1,177 @@
+//! How points add up to games and games to a match.
+//!
+//! A match is a series of games, each won by the first side to reach
+//! [`Rules::points_per_game`], optionally only with a two point lead. Whoever
+//! wins most of the [`Rules::best_of`] games wins the match. With a time
+//! limit, the side ahead when the clock runs out wins as soon as the point in
+//! play is over, and a level match goes to whoever scores next.
+
+use crate::{POINTS_TO_WIN, PaddleSide, Score};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Rules {
+    pub points_per_game: usize,
+    /// Past deuce, a game only ends once a side leads by two points.
+    pub win_by_two: bool,
+    /// Most games a match can take. Odd, so it can't end level, see
+    /// [`Rules::validate`].
+    pub best_of: usize,
+    /// Ticks of play, not counting serve countdowns, before the match is
+    /// decided on the spot.
+    pub time_limit: Option<u32>,
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        Rules {
+            points_per_game: POINTS_TO_WIN,
+            win_by_two: false,
+            best_of: 1,
+            time_limit: None,
+        }
+    }
+}
+
+impl Rules {
+    /// Whether a match played by these rules can be decided, and if not,
+    /// why. Anything reading rules from outside checks them with this.
+    pub fn validate(&self) -> Result<(), &'static str> {
+        if self.points_per_game == 0 {
+            return Err("games need at least one point to win");
+        }
+        if self.best_of.is_multiple_of(2) {
+            return Err("best of an even number of games can end level");
+        }
+        Ok(())
+    }
+
+    pub fn games_to_win(&self) -> usize {
+        self.best_of / 2 + 1
+    }
+
+    /// Who has won the game with this score, if it's over.
+    pub fn game_winner(&self, score: Score) -> Option<PaddleSide> {
+        let leader = score.leader()?;
+        let lead = score.get(leader) - score.get(leader.opposite());
+        let enough_lead = !self.win_by_two || lead >= 2;
+        (score.get(leader) >= self.points_per_game && enough_lead).then_some(leader)
+    }
+
+    /// Who has won the match after `games` were played and `score` points
+    /// into the one in progress, if it's over.
+    pub fn match_winner(&self, games: &[Score], score: Score, time_up: bool) -> Option<PaddleSide> {
+        let won = self.games_won(games);
+        let winner = PaddleSide::ALL
+            .into_iter()
+            .find(|&side| won.get(side) >= self.games_to_win());
+        if winner.is_some() || !time_up {
+            return winner;
+        }
+        // out of time, whoever is ahead wins and a level match keeps going
+        // until someone scores
+        won.leader().or_else(|| score.leader())
+    }
+
+    /// How many of `games` each side won.
+    pub fn games_won(&self, games: &[Score]) -> Score {
+        let mut won = Score::default();
+        for &game in games {
+            if let Some(winner) = self.game_winner(game) {
+                won.add_point(winner);
+            }
+        }
+        won
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn score(left: usize, right: usize) -> Score {
+        Score { left, right }
+    }
+
+    #[test]
+    fn only_rules_that_decide_a_match_are_valid() {
+        assert_eq!(Rules::default().validate(), Ok(()));
+        for rules in [
+            Rules {
+                points_per_game: 0,
+                ..Rules::default()
+            },
+            Rules {
+                best_of: 0,
+                ..Rules::default()
+            },
+            Rules {
+                best_of: 4,
+                ..Rules::default()
+            },
+        ] {
+            assert!(rules.validate().is_err(), "{:?}", rules);
+        }
+    }
+
+    #[test]
+    fn games_go_to_whoever_reaches_the_points_first() {
+        let rules = Rules {
+            points_per_game: 5,
+            ..Rules::default()
+        };
+        assert_eq!(rules.game_winner(score(4, 4)), None);
+        assert_eq!(rules.game_winner(score(5, 4)), Some(PaddleSide::Left));
+        assert_eq!(rules.game_winner(score(2, 5)), Some(PaddleSide::Right));
+    }
+
+    #[test]
+    fn win_by_two_plays_on_past_deuce() {
+        let rules = Rules {
+            points_per_game: 5,
+            win_by_two: true,
+            ..Rules::default()
+        };
+        assert_eq!(rules.game_winner(score(5, 4)), None);
+        assert_eq!(rules.game_winner(score(7, 7)), None);
+        assert_eq!(rules.game_winner(score(7, 9)), Some(PaddleSide::Right));
+        assert_eq!(rules.game_winner(score(5, 3)), Some(PaddleSide::Left));
+    }
+
+    #[test]
+    fn best_of_needs_a_majority_of_games() {
+        let rules = Rules {
+            best_of: 5,
+            ..Rules::default()
+        };
+        let games = [score(3, 1), score(0, 3), score(3, 2)];
+        assert_eq!(rules.match_winner(&games, score(2, 0), false), None);
+        let games = [score(3, 1), score(0, 3), score(3, 2), score(3, 0)];
+        assert_eq!(
+            rules.match_winner(&games, Score::default(), false),
+            Some(PaddleSide::Left)
+        );
+    }
+
+    #[test]
+    fn running_out_of_time_goes_to_the_leader_or_sudden_death() {
+        let rules = Rules {
+            best_of: 3,
+            time_limit: Some(100),
+            ..Rules::default()
+        };
+        // ahead on games wins even if behind in the game in progress
+        let games = [score(1, 3)];
+        assert_eq!(
+            rules.match_winner(&games, score(2, 0), true),
+            Some(PaddleSide::Right)
+        );
+        // level on games, points decide
+        let games = [score(1, 3), score(3, 2)];
+        assert_eq!(
+            rules.match_winner(&games, score(1, 0), true),
+            Some(PaddleSide::Left)
+        );
+        // level on everything, nobody yet
+        assert_eq!(rules.match_winner(&games, score(1, 1), true), None);
+    }
+}
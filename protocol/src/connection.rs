@@ -0,0 +1,226 @@
+use bytes::{Buf, BufMut, BytesMut};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+#[cfg(not(target_arch = "wasm32"))]
+use tokio::net::{
+    TcpStream,
+    tcp::{OwnedReadHalf, OwnedWriteHalf},
+};
+
+use crate::{
+    ClientMessage, Error, MAX_FRAME_LEN, Message, PROTOCOL_VERSION, Result, ServerMessage,
+    check_version, decode_payload, expect_hello,
+};
+
+// Reads can be done on any `AsyncRead` and writes on any `AsyncWrite`, so the
+// two halves of a split stream are connections of their own.
+pub struct Connection<S> {
+    stream: S,
+    buffer: BytesMut,
+}
+
+impl<S> Connection<S> {
+    pub fn new(stream: S) -> Self {
+        Connection {
+            stream,
+            buffer: BytesMut::with_capacity(4096),
+        }
+    }
+
+    pub fn parse_frame<M: Message>(&mut self) -> Result<Option<M>> {
+        if self.buffer.len() < 2 {
+            return Ok(None);
+        }
+
+        let len = u16::from_be_bytes([self.buffer[0], self.buffer[1]]) as usize;
+        if len > MAX_FRAME_LEN {
+            return Err(Error::FrameTooLarge(len));
+        }
+        if self.buffer.len() < 2 + len {
+            return Ok(None);
+        }
+
+        let message = decode_payload(&self.buffer[2..2 + len])?;
+        self.buffer.advance(2 + len);
+
+        Ok(Some(message))
+    }
+}
+
+impl<S: AsyncRead + Unpin> Connection<S> {
+    pub async fn read_message<M: Message>(&mut self) -> Result<Option<M>> {
+        loop {
+            // Attempt to parse a frame from the buffered data. If
+            // enough data has been buffered, the message is
+            // returned
+            if let Some(message) = self.parse_frame()? {
+                return Ok(Some(message));
+            }
+
+            // There is not enough buffered data to read a frame.
+            // Attempt to read more data from the socket.
+            //
+            // On success, the number of bytes is returned. `0`
+            // indicates "end of stream".
+            if 0 == self.stream.read_buf(&mut self.buffer).await? {
+                if self.buffer.is_empty() {
+                    return Ok(None);
+                } else {
+                    return Err(Error::ConnectionReset);
+                }
+            }
+        }
+    }
+
+    async fn read_hello<M: Message>(&mut self) -> Result<u16> {
+        expect_hello(self.read_message::<M>().await)
+    }
+}
+
+impl<S: AsyncWrite + Unpin> Connection<S> {
+    pub async fn write_message<M: Message>(&mut self, message: &M) -> Result<()> {
+        let mut frame = BytesMut::with_capacity(64);
+        frame.put_u16(0);
+        message.encode(&mut frame)?;
+
+        let len = frame.len() - 2;
+        if len > MAX_FRAME_LEN {
+            return Err(Error::FrameTooLarge(len));
+        }
+        frame[..2].copy_from_slice(&(len as u16).to_be_bytes());
+
+        self.stream.write_all(&frame).await?;
+        self.stream.flush().await?;
+
+        Ok(())
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> Connection<S> {
+    pub async fn client_handshake(&mut self) -> Result<()> {
+        self.write_message(&ClientMessage::Hello {
+            version: PROTOCOL_VERSION,
+        })
+        .await?;
+        check_version(self.read_hello::<ServerMessage>().await?)
+    }
+
+    pub async fn server_handshake(&mut self) -> Result<()> {
+        let theirs = self.read_hello::<ClientMessage>().await?;
+        // answer even if the versions differ so the client can tell its
+        // player what's wrong
+        self.write_message(&ServerMessage::Hello {
+            version: PROTOCOL_VERSION,
+        })
+        .await?;
+        check_version(theirs)
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Connection<TcpStream> {
+    pub fn into_split(self) -> (Connection<OwnedReadHalf>, Connection<OwnedWriteHalf>) {
+        let (reader, writer) = self.stream.into_split();
+        // whatever was already buffered belongs to the reading half
+        let reader = Connection {
+            stream: reader,
+            buffer: self.buffer,
+        };
+        (reader, Connection::new(writer))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encode_payload;
+
+    fn frame(message: &ClientMessage) -> Vec<u8> {
+        let payload = encode_payload(message).unwrap();
+        let mut frame = (payload.len() as u16).to_be_bytes().to_vec();
+        frame.extend_from_slice(&payload);
+        frame
+    }
+
+    const PING: ClientMessage = ClientMessage::Ping(9);
+    const INPUT: ClientMessage = ClientMessage::Input {
+        sequence: 3,
+        direction: 1.,
+    };
+
+    #[test]
+    fn frames_split_across_reads_wait_for_the_rest() {
+        let mut connection = Connection::new(());
+        let frame = frame(&INPUT);
+        for byte in &frame[..frame.len() - 1] {
+            connection.buffer.put_u8(*byte);
+            assert!(connection.parse_frame::<ClientMessage>().unwrap().is_none());
+        }
+        connection.buffer.put_u8(frame[frame.len() - 1]);
+        assert_eq!(connection.parse_frame().unwrap(), Some(INPUT));
+        assert!(connection.buffer.is_empty());
+    }
+
+    #[test]
+    fn several_frames_in_one_read_come_out_in_order() {
+        let mut connection = Connection::new(());
+        connection.buffer.extend_from_slice(&frame(&PING));
+        connection.buffer.extend_from_slice(&frame(&INPUT));
+        // and the start of a third
+        connection.buffer.extend_from_slice(&frame(&PING)[..3]);
+
+        assert_eq!(connection.parse_frame().unwrap(), Some(PING));
+        assert_eq!(connection.parse_frame().unwrap(), Some(INPUT));
+        assert_eq!(connection.parse_frame::<ClientMessage>().unwrap(), None);
+    }
+
+    #[test]
+    fn oversized_frames_are_rejected_before_they_arrive() {
+        let mut connection = Connection::new(());
+        let len = MAX_FRAME_LEN as u16 + 1;
+        connection.buffer.extend_from_slice(&len.to_be_bytes());
+        assert!(matches!(
+            connection.parse_frame::<ClientMessage>(),
+            Err(Error::FrameTooLarge(len)) if len == MAX_FRAME_LEN + 1
+        ));
+    }
+
+    #[tokio::test]
+    async fn handshake_reports_a_version_mismatch_on_both_ends() {
+        let (client, server) = tokio::io::duplex(64);
+        let (mut client, mut server) = (Connection::new(client), Connection::new(server));
+
+        let older_client = async {
+            client
+                .write_message(&ClientMessage::Hello {
+                    version: PROTOCOL_VERSION - 1,
+                })
+                .await
+                .unwrap();
+            client.read_message::<ServerMessage>().await
+        };
+        let (answer, server_result) = tokio::join!(older_client, server.server_handshake());
+
+        // the server still says which version it speaks
+        assert_eq!(
+            answer.unwrap(),
+            Some(ServerMessage::Hello {
+                version: PROTOCOL_VERSION
+            })
+        );
+        assert!(matches!(
+            server_result,
+            Err(Error::VersionMismatch { ours, theirs })
+                if ours == PROTOCOL_VERSION && theirs == PROTOCOL_VERSION - 1
+        ));
+    }
+
+    #[tokio::test]
+    async fn handshake_succeeds_on_the_same_version() {
+        let (client, server) = tokio::io::duplex(64);
+        let (mut client, mut server) = (Connection::new(client), Connection::new(server));
+        let (client_result, server_result) =
+            tokio::join!(client.client_handshake(), server.server_handshake());
+        client_result.unwrap();
+        server_result.unwrap();
+    }
+}
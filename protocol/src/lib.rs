@@ -0,0 +1,672 @@
+//! Wire format spoken between the game server and its clients.
+//!
+//! Every message travels in a frame: a big endian `u16` payload length
+//! followed by the payload, whose first byte says which message it is. The
+//! first frame in each direction must be a `Hello` carrying
+//! [`PROTOCOL_VERSION`], see [`Connection::client_handshake`] and
+//! [`Connection::server_handshake`].
+//!
+//! Over WebSocket the framing is left to the socket: each binary message is
+//! one payload as produced by [`encode_payload`], with the same handshake.
+
+use std::{fmt, io};
+
+use bytes::{Buf, BufMut, BytesMut};
+use game::{PaddleSide, Score, Simulation, Vec2, rules::Rules};
+
+mod connection;
+pub use connection::Connection;
+
+/// Bump whenever the encoding of any message changes.
+pub const PROTOCOL_VERSION: u16 = 7;
+
+pub const DEFAULT_PORT: u16 = 7878;
+pub const DEFAULT_WS_PORT: u16 = 7879;
+
+// frames are tiny, anything bigger means the peer isn't speaking this protocol
+pub const MAX_FRAME_LEN: usize = 1024;
+
+// sent before the version so that a peer speaking something else entirely is
+// reported as such instead of as a version mismatch
+const MAGIC: &[u8; 4] = b"PONG";
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    /// The peer closed the connection in the middle of a frame.
+    ConnectionReset,
+    FrameTooLarge(usize),
+    Malformed(&'static str),
+    UnknownMessage(u8),
+    /// The first frame wasn't a `Hello`, the peer isn't a pong client or server.
+    NotPong,
+    VersionMismatch {
+        ours: u16,
+        theirs: u16,
+    },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "{}", e),
+            Error::ConnectionReset => write!(f, "connection reset by peer"),
+            Error::FrameTooLarge(len) => write!(f, "frame of {} bytes is too large", len),
+            Error::Malformed(what) => write!(f, "malformed message: {}", what),
+            Error::UnknownMessage(tag) => write!(f, "unknown message type {}", tag),
+            Error::NotPong => write!(f, "peer does not speak the pong protocol"),
+            Error::VersionMismatch { ours, theirs } => write!(
+                f,
+                "protocol version mismatch: we speak v{} but the peer speaks v{}, update {}",
+                ours,
+                theirs,
+                if ours < theirs {
+                    "this side"
+                } else {
+                    "the peer"
+                }
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+/// Short code a player hands to a friend so they can join the same room.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct RoomCode(pub [u8; RoomCode::LEN]);
+
+impl RoomCode {
+    pub const LEN: usize = 4;
+    /// Letters codes are made of, the ones easily mistaken for digits are left out.
+    pub const ALPHABET: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ";
+
+    /// Reads a code typed by a player, in either case.
+    pub fn parse(code: &str) -> Option<Self> {
+        let code: [u8; RoomCode::LEN] = code.to_ascii_uppercase().as_bytes().try_into().ok()?;
+        code.iter()
+            .all(|letter| RoomCode::ALPHABET.contains(letter))
+            .then_some(RoomCode(code))
+    }
+}
+
+impl fmt::Display for RoomCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // only ever built from the alphabet
+        write!(f, "{}", String::from_utf8_lossy(&self.0))
+    }
+}
+
+/// Everything a client needs to draw a tick of the match.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Snapshot {
+    pub tick: u32,
+    pub paddles: [f32; 2],
+    pub ball_position: Vec2,
+    pub ball_direction: Vec2,
+    pub ball_speed: f32,
+    pub serve_ticks: u16,
+    pub clock_ticks: u32,
+    pub score: Score,
+    /// Sequence number of the last input the server applied to each paddle,
+    /// clients replay anything newer on top of the snapshot.
+    pub last_inputs: [u32; 2],
+}
+
+impl Snapshot {
+    pub fn new(tick: u32, sim: &Simulation, last_inputs: [u32; 2]) -> Self {
+        Snapshot {
+            tick,
+            paddles: sim.paddles.map(|paddle| paddle.position.y),
+            ball_position: sim.ball.position,
+            ball_direction: sim.ball.direction,
+            ball_speed: sim.ball.speed,
+            serve_ticks: sim.serve_ticks as u16,
+            clock_ticks: sim.clock_ticks,
+            score: sim.score,
+            last_inputs,
+        }
+    }
+
+    pub fn apply(&self, sim: &mut Simulation) {
+        for (paddle, y) in sim.paddles.iter_mut().zip(self.paddles) {
+            paddle.position.y = y;
+        }
+        sim.ball.position = self.ball_position;
+        sim.ball.direction = self.ball_direction;
+        sim.ball.speed = self.ball_speed;
+        sim.serve_ticks = self.serve_ticks as u32;
+        sim.clock_ticks = self.clock_ticks;
+        sim.score = self.score;
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ClientMessage {
+    Hello {
+        version: u16,
+    },
+    /// Vertical movement of the sender's paddle for one tick, in `-1.0..=1.0`.
+    /// Sequence numbers count up from 1 and are acknowledged in snapshots.
+    Input {
+        sequence: u32,
+        direction: f32,
+    },
+    Ping(u32),
+    Pong(u32),
+    // lobby requests, exactly one of these follows the handshake
+    /// Plays whoever else asked for a quick match.
+    QuickMatch,
+    /// Opens a room played by these rules, the server answers with its code.
+    CreateRoom(Rules),
+    JoinRoom(RoomCode),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ServerMessage {
+    Hello {
+        version: u16,
+    },
+    /// Sent once when the match starts, tells the client which paddle it
+    /// controls and the rules the match is played by.
+    Welcome(PaddleSide, Rules),
+    Snapshot(Snapshot),
+    PointScored(PaddleSide),
+    GameOver(PaddleSide),
+    Ping(u32),
+    Pong(u32),
+    /// The room asked for is open, the match starts once someone joins it.
+    RoomCreated(RoomCode),
+    /// No room has this code, the server hangs up after sending this.
+    RoomNotFound(RoomCode),
+    /// Final score of a game that just ended, matches played over several
+    /// games send one per game.
+    GameWon(Score),
+    /// The ball bounced off this side's paddle, clients only see where it
+    /// ended up in the next snapshot.
+    PaddleHit(PaddleSide),
+    WallHit,
+}
+
+/// A message that can be put in a frame.
+pub trait Message: Sized {
+    /// Fails for values the wire format has no room for.
+    fn encode(&self, buf: &mut BytesMut) -> Result<()>;
+    fn decode(buf: &mut &[u8]) -> Result<Self>;
+    fn hello(&self) -> Option<u16>;
+}
+
+impl Message for ClientMessage {
+    fn encode(&self, buf: &mut BytesMut) -> Result<()> {
+        match self {
+            ClientMessage::Hello { version } => encode_hello(buf, *version),
+            ClientMessage::Input {
+                sequence,
+                direction,
+            } => {
+                buf.put_u8(1);
+                buf.put_u32(*sequence);
+                buf.put_f32(*direction);
+            }
+            ClientMessage::Ping(n) => {
+                buf.put_u8(2);
+                buf.put_u32(*n);
+            }
+            ClientMessage::Pong(n) => {
+                buf.put_u8(3);
+                buf.put_u32(*n);
+            }
+            ClientMessage::QuickMatch => buf.put_u8(4),
+            ClientMessage::CreateRoom(rules) => {
+                buf.put_u8(5);
+                put_rules(buf, rules)?;
+            }
+            ClientMessage::JoinRoom(code) => {
+                buf.put_u8(6);
+                put_room_code(buf, *code);
+            }
+        }
+        Ok(())
+    }
+
+    fn decode(buf: &mut &[u8]) -> Result<Self> {
+        Ok(match get_u8(buf)? {
+            0 => ClientMessage::Hello {
+                version: decode_hello(buf)?,
+            },
+            1 => ClientMessage::Input {
+                sequence: get_u32(buf)?,
+                direction: get_f32(buf)?,
+            },
+            2 => ClientMessage::Ping(get_u32(buf)?),
+            3 => ClientMessage::Pong(get_u32(buf)?),
+            4 => ClientMessage::QuickMatch,
+            5 => ClientMessage::CreateRoom(get_rules(buf)?),
+            6 => ClientMessage::JoinRoom(get_room_code(buf)?),
+            tag => return Err(Error::UnknownMessage(tag)),
+        })
+    }
+
+    fn hello(&self) -> Option<u16> {
+        match self {
+            ClientMessage::Hello { version } => Some(*version),
+            _ => None,
+        }
+    }
+}
+
+impl Message for ServerMessage {
+    fn encode(&self, buf: &mut BytesMut) -> Result<()> {
+        match self {
+            ServerMessage::Hello { version } => encode_hello(buf, *version),
+            ServerMessage::Welcome(side, rules) => {
+                buf.put_u8(1);
+                put_side(buf, *side);
+                put_rules(buf, rules)?;
+            }
+            ServerMessage::Snapshot(snapshot) => {
+                buf.put_u8(2);
+                buf.put_u32(snapshot.tick);
+                buf.put_f32(snapshot.paddles[0]);
+                buf.put_f32(snapshot.paddles[1]);
+                buf.put_f32(snapshot.ball_position.x);
+                buf.put_f32(snapshot.ball_position.y);
+                buf.put_f32(snapshot.ball_direction.x);
+                buf.put_f32(snapshot.ball_direction.y);
+                buf.put_f32(snapshot.ball_speed);
+                buf.put_u16(snapshot.serve_ticks);
+                buf.put_u32(snapshot.clock_ticks);
+                put_score(buf, snapshot.score)?;
+                buf.put_u32(snapshot.last_inputs[0]);
+                buf.put_u32(snapshot.last_inputs[1]);
+            }
+            ServerMessage::PointScored(side) => {
+                buf.put_u8(3);
+                put_side(buf, *side);
+            }
+            ServerMessage::GameOver(side) => {
+                buf.put_u8(4);
+                put_side(buf, *side);
+            }
+            ServerMessage::Ping(n) => {
+                buf.put_u8(5);
+                buf.put_u32(*n);
+            }
+            ServerMessage::Pong(n) => {
+                buf.put_u8(6);
+                buf.put_u32(*n);
+            }
+            ServerMessage::RoomCreated(code) => {
+                buf.put_u8(7);
+                put_room_code(buf, *code);
+            }
+            ServerMessage::RoomNotFound(code) => {
+                buf.put_u8(8);
+                put_room_code(buf, *code);
+            }
+            ServerMessage::GameWon(score) => {
+                buf.put_u8(9);
+                put_score(buf, *score)?;
+            }
+            ServerMessage::PaddleHit(side) => {
+                buf.put_u8(10);
+                put_side(buf, *side);
+            }
+            ServerMessage::WallHit => buf.put_u8(11),
+        }
+        Ok(())
+    }
+
+    fn decode(buf: &mut &[u8]) -> Result<Self> {
+        Ok(match get_u8(buf)? {
+            0 => ServerMessage::Hello {
+                version: decode_hello(buf)?,
+            },
+            1 => ServerMessage::Welcome(get_side(buf)?, get_rules(buf)?),
+            2 => ServerMessage::Snapshot(Snapshot {
+                tick: get_u32(buf)?,
+                paddles: [get_f32(buf)?, get_f32(buf)?],
+                ball_position: Vec2::new(get_f32(buf)?, get_f32(buf)?),
+                ball_direction: Vec2::new(get_f32(buf)?, get_f32(buf)?),
+                ball_speed: get_f32(buf)?,
+                serve_ticks: get_u16(buf)?,
+                clock_ticks: get_u32(buf)?,
+                score: get_score(buf)?,
+                last_inputs: [get_u32(buf)?, get_u32(buf)?],
+            }),
+            3 => ServerMessage::PointScored(get_side(buf)?),
+            4 => ServerMessage::GameOver(get_side(buf)?),
+            5 => ServerMessage::Ping(get_u32(buf)?),
+            6 => ServerMessage::Pong(get_u32(buf)?),
+            7 => ServerMessage::RoomCreated(get_room_code(buf)?),
+            8 => ServerMessage::RoomNotFound(get_room_code(buf)?),
+            9 => ServerMessage::GameWon(get_score(buf)?),
+            10 => ServerMessage::PaddleHit(get_side(buf)?),
+            11 => ServerMessage::WallHit,
+            tag => return Err(Error::UnknownMessage(tag)),
+        })
+    }
+
+    fn hello(&self) -> Option<u16> {
+        match self {
+            ServerMessage::Hello { version } => Some(*version),
+            _ => None,
+        }
+    }
+}
+
+pub fn encode_payload<M: Message>(message: &M) -> Result<BytesMut> {
+    let mut payload = BytesMut::with_capacity(64);
+    message.encode(&mut payload)?;
+    if payload.len() > MAX_FRAME_LEN {
+        return Err(Error::FrameTooLarge(payload.len()));
+    }
+    Ok(payload)
+}
+
+pub fn decode_payload<M: Message>(mut payload: &[u8]) -> Result<M> {
+    let message = M::decode(&mut payload)?;
+    if !payload.is_empty() {
+        return Err(Error::Malformed("trailing bytes after message"));
+    }
+    Ok(message)
+}
+
+/// Turns the first message read from a peer into the version it speaks.
+pub fn expect_hello<M: Message>(first: Result<Option<M>>) -> Result<u16> {
+    match first {
+        Ok(Some(message)) => message.hello().ok_or(Error::NotPong),
+        Ok(None) => Err(Error::ConnectionReset),
+        Err(Error::Io(e)) => Err(Error::Io(e)),
+        Err(_) => Err(Error::NotPong),
+    }
+}
+
+pub fn check_version(theirs: u16) -> Result<()> {
+    if theirs != PROTOCOL_VERSION {
+        return Err(Error::VersionMismatch {
+            ours: PROTOCOL_VERSION,
+            theirs,
+        });
+    }
+    Ok(())
+}
+
+// Hello is tag 0 in both directions and must keep this layout forever, it's
+// the one message peers on different versions have to agree on.
+fn encode_hello(buf: &mut BytesMut, version: u16) {
+    buf.put_u8(0);
+    buf.put_slice(MAGIC);
+    buf.put_u16(version);
+}
+
+fn decode_hello(buf: &mut &[u8]) -> Result<u16> {
+    if buf.remaining() < MAGIC.len() || &buf[..MAGIC.len()] != MAGIC {
+        return Err(Error::NotPong);
+    }
+    buf.advance(MAGIC.len());
+    get_u16(buf)
+}
+
+fn put_side(buf: &mut BytesMut, side: PaddleSide) {
+    buf.put_u8(match side {
+        PaddleSide::Left => 0,
+        PaddleSide::Right => 1,
+    });
+}
+
+fn get_side(buf: &mut &[u8]) -> Result<PaddleSide> {
+    match get_u8(buf)? {
+        0 => Ok(PaddleSide::Left),
+        1 => Ok(PaddleSide::Right),
+        _ => Err(Error::Malformed("unknown paddle side")),
+    }
+}
+
+fn put_room_code(buf: &mut BytesMut, code: RoomCode) {
+    buf.put_slice(&code.0);
+}
+
+fn get_room_code(buf: &mut &[u8]) -> Result<RoomCode> {
+    need(buf, RoomCode::LEN)?;
+    let mut code = [0; RoomCode::LEN];
+    buf.copy_to_slice(&mut code);
+    if !code
+        .iter()
+        .all(|letter| RoomCode::ALPHABET.contains(letter))
+    {
+        return Err(Error::Malformed("invalid room code"));
+    }
+    Ok(RoomCode(code))
+}
+
+fn put_score(buf: &mut BytesMut, score: Score) -> Result<()> {
+    for points in [score.left, score.right] {
+        let points = u16::try_from(points).map_err(|_| Error::Malformed("score is too high"))?;
+        buf.put_u16(points);
+    }
+    Ok(())
+}
+
+fn get_score(buf: &mut &[u8]) -> Result<Score> {
+    Ok(Score {
+        left: get_u16(buf)? as usize,
+        right: get_u16(buf)? as usize,
+    })
+}
+
+// no time limit is sent as 0
+fn put_rules(buf: &mut BytesMut, rules: &Rules) -> Result<()> {
+    let too_long = |_| Error::Malformed("rules make too long a match");
+    buf.put_u8(u8::try_from(rules.points_per_game).map_err(too_long)?);
+    buf.put_u8(rules.win_by_two.into());
+    buf.put_u8(u8::try_from(rules.best_of).map_err(too_long)?);
+    buf.put_u32(rules.time_limit.unwrap_or(0));
+    Ok(())
+}
+
+fn get_rules(buf: &mut &[u8]) -> Result<Rules> {
+    let points_per_game = get_u8(buf)? as usize;
+    let win_by_two = match get_u8(buf)? {
+        0 => false,
+        1 => true,
+        _ => return Err(Error::Malformed("win by two is not a bool")),
+    };
+    let best_of = get_u8(buf)? as usize;
+    let time_limit = get_u32(buf)?;
+    let rules = Rules {
+        points_per_game,
+        win_by_two,
+        best_of,
+        time_limit: (time_limit > 0).then_some(time_limit),
+    };
+    rules.validate().map_err(Error::Malformed)?;
+    Ok(rules)
+}
+
+// the `Buf` getters panic when there isn't enough data, these don't
+
+fn need(buf: &&[u8], len: usize) -> Result<()> {
+    if buf.remaining() < len {
+        return Err(Error::Malformed("message is truncated"));
+    }
+    Ok(())
+}
+
+fn get_u8(buf: &mut &[u8]) -> Result<u8> {
+    need(buf, 1)?;
+    Ok(buf.get_u8())
+}
+
+fn get_u16(buf: &mut &[u8]) -> Result<u16> {
+    need(buf, 2)?;
+    Ok(buf.get_u16())
+}
+
+fn get_u32(buf: &mut &[u8]) -> Result<u32> {
+    need(buf, 4)?;
+    Ok(buf.get_u32())
+}
+
+// NaN and infinities would spread through whatever simulation they reach
+fn get_f32(buf: &mut &[u8]) -> Result<f32> {
+    need(buf, 4)?;
+    let value = buf.get_f32();
+    if !value.is_finite() {
+        return Err(Error::Malformed("number is not finite"));
+    }
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules() -> Rules {
+        Rules {
+            points_per_game: 11,
+            win_by_two: true,
+            best_of: 5,
+            time_limit: Some(3600),
+        }
+    }
+
+    fn client_messages() -> Vec<ClientMessage> {
+        vec![
+            ClientMessage::Hello {
+                version: PROTOCOL_VERSION,
+            },
+            ClientMessage::Input {
+                sequence: 7,
+                direction: -0.5,
+            },
+            ClientMessage::Ping(1),
+            ClientMessage::Pong(2),
+            ClientMessage::QuickMatch,
+            ClientMessage::CreateRoom(rules()),
+            ClientMessage::CreateRoom(Rules::default()),
+            ClientMessage::JoinRoom(RoomCode(*b"ABCD")),
+        ]
+    }
+
+    fn server_messages() -> Vec<ServerMessage> {
+        let score = Score { left: 3, right: 11 };
+        vec![
+            ServerMessage::Hello {
+                version: PROTOCOL_VERSION,
+            },
+            ServerMessage::Welcome(PaddleSide::Right, rules()),
+            ServerMessage::Snapshot(Snapshot {
+                tick: 100,
+                paddles: [-20., 35.5],
+                ball_position: Vec2::new(1., -2.),
+                ball_direction: Vec2::new(0.6, 0.8),
+                ball_speed: 1800.,
+                serve_ticks: 12,
+                clock_ticks: 99,
+                score,
+                last_inputs: [4, 5],
+            }),
+            ServerMessage::PointScored(PaddleSide::Left),
+            ServerMessage::GameOver(PaddleSide::Right),
+            ServerMessage::Ping(3),
+            ServerMessage::Pong(4),
+            ServerMessage::RoomCreated(RoomCode(*b"WXYZ")),
+            ServerMessage::RoomNotFound(RoomCode(*b"QQQQ")),
+            ServerMessage::GameWon(score),
+            ServerMessage::PaddleHit(PaddleSide::Left),
+            ServerMessage::WallHit,
+        ]
+    }
+
+    fn round_trip<M: Message + PartialEq + fmt::Debug>(messages: Vec<M>) {
+        for message in messages {
+            let payload = encode_payload(&message).unwrap();
+            assert_eq!(decode_payload::<M>(&payload).unwrap(), message);
+
+            // cut short anywhere, it's reported rather than misread
+            for len in 0..payload.len() {
+                assert!(
+                    decode_payload::<M>(&payload[..len]).is_err(),
+                    "{:?} cut to {} bytes",
+                    message,
+                    len
+                );
+            }
+            let mut longer = payload.clone();
+            longer.put_u8(0);
+            assert!(matches!(
+                decode_payload::<M>(&longer),
+                Err(Error::Malformed("trailing bytes after message"))
+            ));
+        }
+    }
+
+    #[test]
+    fn every_message_survives_the_round_trip() {
+        round_trip(client_messages());
+        round_trip(server_messages());
+    }
+
+    #[test]
+    fn unknown_tags_are_reported() {
+        assert!(matches!(
+            decode_payload::<ClientMessage>(&[200]),
+            Err(Error::UnknownMessage(200))
+        ));
+        assert!(matches!(
+            decode_payload::<ServerMessage>(&[200]),
+            Err(Error::UnknownMessage(200))
+        ));
+    }
+
+    #[test]
+    fn numbers_that_are_not_finite_are_rejected() {
+        for direction in [f32::NAN, f32::INFINITY, f32::NEG_INFINITY] {
+            let payload = encode_payload(&ClientMessage::Input {
+                sequence: 1,
+                direction,
+            })
+            .unwrap();
+            assert!(matches!(
+                decode_payload::<ClientMessage>(&payload),
+                Err(Error::Malformed("number is not finite"))
+            ));
+        }
+    }
+
+    #[test]
+    fn rules_that_cant_decide_a_match_are_rejected() {
+        let even = Rules {
+            best_of: 2,
+            ..Rules::default()
+        };
+        let payload = encode_payload(&ClientMessage::CreateRoom(even)).unwrap();
+        assert!(matches!(
+            decode_payload::<ClientMessage>(&payload),
+            Err(Error::Malformed(reason)) if Err(reason) == even.validate()
+        ));
+    }
+
+    #[test]
+    fn values_too_large_for_the_wire_are_not_sent() {
+        let rules = Rules {
+            points_per_game: 256,
+            ..Rules::default()
+        };
+        assert!(encode_payload(&ClientMessage::CreateRoom(rules)).is_err());
+        let score = Score {
+            left: 70_000,
+            right: 0,
+        };
+        assert!(encode_payload(&ServerMessage::GameWon(score)).is_err());
+    }
+}
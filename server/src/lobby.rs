@@ -0,0 +1,282 @@
+// Where players end up after the handshake. They either ask for a quick match
+// with whoever else is waiting, or open a room and share its code so a friend
+// can join them.
+
+use std::{collections::HashMap, path::PathBuf};
+
+use game::rules::Rules;
+use protocol::{ClientMessage, RoomCode, ServerMessage};
+use rand::seq::IndexedRandom;
+use tokio::sync::{mpsc, oneshot};
+
+use crate::{session, transport::Player};
+
+enum Request {
+    QuickMatch,
+    CreateRoom(Rules),
+    JoinRoom(RoomCode),
+}
+
+// Each waiting player sits in a room of its own until an opponent is sent
+// over, the quick match room just doesn't have a code and uses the default
+// rules. A room whose host left is closed and counts as gone.
+type Room = oneshot::Sender<Player>;
+
+// `replays` is where matches are recorded, if anywhere
+pub async fn run(mut players: mpsc::Receiver<Player>, replays: Option<PathBuf>) {
+    let (requests_tx, mut requests) = mpsc::unbounded_channel();
+    let mut rooms: HashMap<RoomCode, Room> = HashMap::new();
+    let mut quick_match: Option<Room> = None;
+
+    loop {
+        let (player, request) = tokio::select! {
+            Some(player) = players.recv() => {
+                tokio::spawn(read_request(player, requests_tx.clone()));
+                continue;
+            }
+            Some(request) = requests.recv() => request,
+        };
+
+        match request {
+            Request::QuickMatch => {
+                let player = match quick_match.take() {
+                    Some(host) => match host.send(player) {
+                        Ok(()) => continue,
+                        Err(player) => player,
+                    },
+                    None => player,
+                };
+                println!("{} is waiting for a quick match", player.addr);
+                quick_match = Some(open_room(player, None, Rules::default(), replays.clone()));
+            }
+            Request::CreateRoom(rules) => {
+                rooms.retain(|_, host| !host.is_closed());
+                let code = loop {
+                    let code = random_code();
+                    if !rooms.contains_key(&code) {
+                        break code;
+                    }
+                };
+                println!("{} opened room {}", player.addr, code);
+                rooms.insert(code, open_room(player, Some(code), rules, replays.clone()));
+            }
+            Request::JoinRoom(code) => {
+                let mut player = match rooms.remove(&code) {
+                    // fails if the host has left in the meantime
+                    Some(host) => match host.send(player) {
+                        Ok(()) => continue,
+                        Err(player) => player,
+                    },
+                    None => player,
+                };
+                println!("{} asked for unknown room {}", player.addr, code);
+                tokio::spawn(async move {
+                    let _ = player
+                        .writer
+                        .write_message(&ServerMessage::RoomNotFound(code))
+                        .await;
+                });
+            }
+        }
+    }
+}
+
+async fn read_request(mut player: Player, requests: mpsc::UnboundedSender<(Player, Request)>) {
+    let request = loop {
+        match player.reader.read_message().await {
+            Ok(Some(ClientMessage::QuickMatch)) => break Request::QuickMatch,
+            Ok(Some(ClientMessage::CreateRoom(rules))) => break Request::CreateRoom(rules),
+            Ok(Some(ClientMessage::JoinRoom(code))) => break Request::JoinRoom(code),
+            Ok(Some(ClientMessage::Ping(n))) => {
+                if player
+                    .writer
+                    .write_message(&ServerMessage::Pong(n))
+                    .await
+                    .is_err()
+                {
+                    return;
+                }
+            }
+            Ok(Some(message)) => {
+                eprintln!("{} sent {:?} before joining a match", player.addr, message);
+                return;
+            }
+            Ok(None) => return,
+            Err(e) => {
+                eprintln!("{}: {}", player.addr, e);
+                return;
+            }
+        }
+    };
+
+    let _ = requests.send((player, request));
+}
+
+fn open_room(host: Player, code: Option<RoomCode>, rules: Rules, replays: Option<PathBuf>) -> Room {
+    let (room, opponent) = oneshot::channel();
+    tokio::spawn(wait_for_opponent(host, code, rules, replays, opponent));
+    room
+}
+
+// The host gets the left paddle. Keeps answering the host's pings meanwhile,
+// and notices when they give up waiting. Only reading is raced against the
+// opponent turning up, a pong cut off halfway would garble what follows it.
+async fn wait_for_opponent(
+    mut host: Player,
+    code: Option<RoomCode>,
+    rules: Rules,
+    replays: Option<PathBuf>,
+    mut opponent: oneshot::Receiver<Player>,
+) {
+    if let Some(code) = code {
+        let created = ServerMessage::RoomCreated(code);
+        if host.writer.write_message(&created).await.is_err() {
+            return;
+        }
+    }
+
+    loop {
+        let message = tokio::select! {
+            opponent = &mut opponent => {
+                // without an opponent the lobby itself is gone
+                if let Ok(opponent) = opponent {
+                    println!("{} joined {}, starting match", opponent.addr, host.addr);
+                    session::run([host, opponent], rules, replays).await;
+                }
+                return;
+            }
+            message = host.reader.read_message() => message,
+        };
+        match message {
+            Ok(Some(ClientMessage::Ping(n))) => {
+                let pong = ServerMessage::Pong(n);
+                if host.writer.write_message(&pong).await.is_err() {
+                    break;
+                }
+            }
+            Ok(Some(_)) => {}
+            Ok(None) | Err(_) => break,
+        }
+    }
+    println!("{} left before the match started", host.addr);
+}
+
+fn random_code() -> RoomCode {
+    let mut rng = rand::rng();
+    RoomCode(std::array::from_fn(|_| {
+        *RoomCode::ALPHABET.choose(&mut rng).unwrap()
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use game::PaddleSide;
+    use protocol::Connection;
+    use tokio::net::{TcpListener, TcpStream};
+
+    use crate::transport;
+
+    type Client = Connection<TcpStream>;
+
+    // a lobby and a way to get players into it like main does, minus the
+    // handshake which isn't the lobby's business
+    async fn start_lobby() -> (TcpListener, mpsc::Sender<Player>) {
+        let (players_tx, players) = mpsc::channel(1);
+        tokio::spawn(run(players, None));
+        (TcpListener::bind("127.0.0.1:0").await.unwrap(), players_tx)
+    }
+
+    async fn join(listener: &TcpListener, players: &mpsc::Sender<Player>) -> Client {
+        let client = Connection::new(
+            TcpStream::connect(listener.local_addr().unwrap())
+                .await
+                .unwrap(),
+        );
+        let (socket, addr) = listener.accept().await.unwrap();
+        let (reader, writer) = Connection::new(socket).into_split();
+        let player = Player {
+            addr,
+            reader: transport::Reader::Tcp(reader),
+            writer: transport::Writer::Tcp(writer),
+        };
+        players.send(player).await.unwrap();
+        client
+    }
+
+    async fn recv(client: &mut Client) -> Option<ServerMessage> {
+        client.read_message().await.unwrap()
+    }
+
+    async fn send(client: &mut Client, message: &ClientMessage) {
+        client.write_message(message).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn pairs_players_by_room_code() {
+        let (listener, players) = start_lobby().await;
+        let mut host = join(&listener, &players).await;
+        let mut guest = join(&listener, &players).await;
+
+        // the host's rules apply to both players
+        let rules = Rules {
+            best_of: 3,
+            ..Rules::default()
+        };
+        send(&mut host, &ClientMessage::CreateRoom(rules)).await;
+        let Some(ServerMessage::RoomCreated(code)) = recv(&mut host).await else {
+            panic!("expected the room code");
+        };
+
+        send(&mut guest, &ClientMessage::JoinRoom(code)).await;
+        assert_eq!(
+            recv(&mut host).await,
+            Some(ServerMessage::Welcome(PaddleSide::Left, rules))
+        );
+        assert_eq!(
+            recv(&mut guest).await,
+            Some(ServerMessage::Welcome(PaddleSide::Right, rules))
+        );
+    }
+
+    #[tokio::test]
+    async fn answers_pings_until_the_opponent_arrives() {
+        let (listener, players) = start_lobby().await;
+        let mut host = join(&listener, &players).await;
+        let mut guest = join(&listener, &players).await;
+
+        send(&mut host, &ClientMessage::CreateRoom(Rules::default())).await;
+        let Some(ServerMessage::RoomCreated(code)) = recv(&mut host).await else {
+            panic!("expected the room code");
+        };
+        for n in 0..3 {
+            send(&mut host, &ClientMessage::Ping(n)).await;
+            assert_eq!(recv(&mut host).await, Some(ServerMessage::Pong(n)));
+        }
+
+        send(&mut host, &ClientMessage::Ping(3)).await;
+        send(&mut guest, &ClientMessage::JoinRoom(code)).await;
+        // the pong may or may not make it out first, but never garbled
+        let mut message = recv(&mut host).await;
+        if message == Some(ServerMessage::Pong(3)) {
+            message = recv(&mut host).await;
+        }
+        assert_eq!(
+            message,
+            Some(ServerMessage::Welcome(PaddleSide::Left, Rules::default()))
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_unknown_room_codes() {
+        let (listener, players) = start_lobby().await;
+        let mut guest = join(&listener, &players).await;
+
+        let code = RoomCode::parse("ABCD").unwrap();
+        send(&mut guest, &ClientMessage::JoinRoom(code)).await;
+        assert_eq!(
+            recv(&mut guest).await,
+            Some(ServerMessage::RoomNotFound(code))
+        );
+    }
+}
@@ -0,0 +1,72 @@
+use std::path::PathBuf;
+
+use protocol::{Connection, DEFAULT_PORT, DEFAULT_WS_PORT};
+use tokio::{net::TcpListener, sync::mpsc, time};
+use transport::Player;
+
+mod lobby;
+mod session;
+mod transport;
+mod ws;
+
+#[tokio::main]
+async fn main() {
+    // `--replays <dir>` records every match to a file in `dir`
+    let mut args: Vec<_> = std::env::args().skip(1).collect();
+    let replays = args
+        .iter()
+        .position(|arg| arg == "--replays")
+        .filter(|&i| i + 1 < args.len())
+        .map(|i| PathBuf::from(args.drain(i..=i + 1).nth(1).unwrap()));
+    let mut args = args.into_iter();
+    let addr = args
+        .next()
+        .unwrap_or_else(|| format!("0.0.0.0:{}", DEFAULT_PORT));
+    let ws_addr = args
+        .next()
+        .unwrap_or_else(|| format!("0.0.0.0:{}", DEFAULT_WS_PORT));
+
+    let listener = TcpListener::bind(&addr).await.unwrap();
+    println!("listening on {}", addr);
+    let ws_listener = TcpListener::bind(&ws_addr).await.unwrap();
+    println!("listening for websockets on {}", ws_addr);
+
+    let (players_tx, players) = mpsc::channel(16);
+
+    tokio::spawn(ws::listen(ws_listener, players_tx.clone()));
+    tokio::spawn(async move {
+        loop {
+            let (socket, peer) = match listener.accept().await {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    eprintln!("couldn't accept a connection: {}", e);
+                    time::sleep(transport::ACCEPT_RETRY_DELAY).await;
+                    continue;
+                }
+            };
+            let players_tx = players_tx.clone();
+
+            // handshake off the accept loop, a slow client shouldn't hold up everyone else
+            tokio::spawn(async move {
+                let mut connection = Connection::new(socket);
+                match connection.server_handshake().await {
+                    Ok(()) => {
+                        let (reader, writer) = connection.into_split();
+                        let player = Player {
+                            addr: peer,
+                            reader: transport::Reader::Tcp(reader),
+                            writer: transport::Writer::Tcp(writer),
+                        };
+                        let _ = players_tx.send(player).await;
+                    }
+                    Err(e) => eprintln!("rejected {}: {}", peer, e),
+                }
+            });
+        }
+    });
+
+    if let Some(replays) = &replays {
+        println!("recording matches to {}", replays.display());
+    }
+    lobby::run(players, replays).await;
+}
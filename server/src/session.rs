@@ -0,0 +1,273 @@
+use std::{
+    collections::VecDeque,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use game::{
+    Config, Event as GameEvent, Input, PaddleSide, Simulation, TICK_DT, TICK_RATE, replay::Replay,
+    rules::Rules,
+};
+use protocol::{ClientMessage, ServerMessage, Snapshot};
+use tokio::{
+    fs,
+    io::AsyncWriteExt,
+    sync::{broadcast, mpsc},
+    time,
+};
+
+use crate::transport::{Player, Reader, Writer};
+
+// how long play stops after a point, matches the client's PointScoredTimer
+const POINT_SCORED_TICKS: u32 = TICK_RATE as u32;
+
+// clients send one input per tick, a client whose clock runs ahead of ours
+// would otherwise build up more and more lag
+const MAX_BUFFERED_INPUTS: usize = 8;
+
+enum Event {
+    Input {
+        side: PaddleSide,
+        sequence: u32,
+        direction: f32,
+    },
+    Disconnected(PaddleSide),
+}
+
+enum Phase {
+    Playing,
+    PointScored { ticks_left: u32 },
+}
+
+// Runs one match to completion. The server owns the only real simulation,
+// clients just send their paddle input and draw whatever snapshots they get.
+// The match is recorded to a file in `replays` when there is one.
+pub async fn run(players: [Player; 2], rules: Rules, replays: Option<PathBuf>) {
+    let (events_tx, mut events) = mpsc::unbounded_channel();
+    let (messages, _) = broadcast::channel(64);
+
+    for (side, player) in PaddleSide::ALL.into_iter().zip(players) {
+        // replies that only concern this player, like pongs
+        let (replies_tx, replies) = mpsc::unbounded_channel();
+        tokio::spawn(read_inputs(
+            side,
+            player.reader,
+            events_tx.clone(),
+            replies_tx,
+        ));
+        tokio::spawn(write_messages(
+            side,
+            rules,
+            player.writer,
+            messages.subscribe(),
+            replies,
+            events_tx.clone(),
+        ));
+    }
+
+    let seed = rand::random();
+    println!("starting match with seed {}", seed);
+    // when it started tells apart matches that happen to have the same seed
+    let started = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+    let replay_path = replays.map(|dir| dir.join(format!("{}-{}.replay", started, seed)));
+    let config = Config {
+        rules,
+        ..Config::default()
+    };
+    let mut sim = Simulation::with_config(seed, config);
+    let mut replay = Replay::new(seed, config);
+    let mut input = Input::default();
+    let mut buffered_inputs: [VecDeque<(u32, f32)>; 2] = Default::default();
+    let mut last_inputs = [0; 2];
+    let mut phase = Phase::Playing;
+    let mut interval = time::interval(Duration::from_secs_f32(TICK_DT));
+
+    for tick in 0u32.. {
+        interval.tick().await;
+
+        while let Ok(event) = events.try_recv() {
+            match event {
+                Event::Input {
+                    side,
+                    sequence,
+                    direction,
+                } => {
+                    let buffer = &mut buffered_inputs[side.index()];
+                    buffer.push_back((sequence, direction));
+                    if buffer.len() > MAX_BUFFERED_INPUTS {
+                        buffer.pop_front();
+                    }
+                }
+                Event::Disconnected(side) => {
+                    println!("{} player left, ending match", side);
+                    let _ = messages.send(ServerMessage::GameOver(side.opposite()));
+                    if let Some(path) = &replay_path {
+                        save_replay(path, &replay).await;
+                    }
+                    return;
+                }
+            }
+        }
+
+        match &mut phase {
+            // nothing is sent during the pause, clients resume playing on
+            // the first snapshot after a point
+            Phase::PointScored { ticks_left } if *ticks_left > 0 => {
+                *ticks_left -= 1;
+                continue;
+            }
+            _ => {
+                phase = Phase::Playing;
+                // one input per tick, exactly like the client predicted it.
+                // Without a fresh one the paddle keeps doing what it last did
+                for side in PaddleSide::ALL {
+                    if let Some((sequence, direction)) = buffered_inputs[side.index()].pop_front() {
+                        input.set(side, direction);
+                        last_inputs[side.index()] = sequence;
+                    }
+                }
+                replay.record(input);
+                let scored = sim.step(&input);
+                for event in &sim.events {
+                    let message = match *event {
+                        GameEvent::PaddleHit(side) => ServerMessage::PaddleHit(side),
+                        GameEvent::WallHit => ServerMessage::WallHit,
+                        GameEvent::GameWon(_) => match sim.games.last() {
+                            Some(&score) => ServerMessage::GameWon(score),
+                            None => continue,
+                        },
+                        // these follow the snapshot with the new score, below
+                        GameEvent::PointScored(_) | GameEvent::MatchWon(_) => continue,
+                    };
+                    let _ = messages.send(message);
+                }
+                if let Some(scorer) = scored {
+                    let _ = messages.send(ServerMessage::Snapshot(Snapshot::new(
+                        tick,
+                        &sim,
+                        last_inputs,
+                    )));
+                    let _ = messages.send(ServerMessage::PointScored(scorer));
+                    if let Some(winner) = sim.winner() {
+                        println!("{} player won", winner);
+                        let _ = messages.send(ServerMessage::GameOver(winner));
+                        if let Some(path) = &replay_path {
+                            save_replay(path, &replay).await;
+                        }
+                        return;
+                    }
+                    phase = Phase::PointScored {
+                        ticks_left: POINT_SCORED_TICKS,
+                    };
+                    continue;
+                }
+            }
+        }
+
+        let _ = messages.send(ServerMessage::Snapshot(Snapshot::new(
+            tick,
+            &sim,
+            last_inputs,
+        )));
+    }
+}
+
+// Matches cut short by a disconnect are kept too, they're the ones most
+// likely to be looked into. Never overwrites a file that's already there.
+async fn save_replay(path: &Path, replay: &Replay) {
+    let result = async {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).await?;
+        }
+        let mut file = fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(path)
+            .await?;
+        file.write_all(replay.encode().as_bytes()).await
+    };
+    match result.await {
+        Ok(()) => println!("saved replay to {}", path.display()),
+        Err(e) => eprintln!("couldn't save replay to {}: {}", path.display(), e),
+    }
+}
+
+async fn read_inputs(
+    side: PaddleSide,
+    mut reader: Reader,
+    events: mpsc::UnboundedSender<Event>,
+    replies: mpsc::UnboundedSender<ServerMessage>,
+) {
+    loop {
+        match reader.read_message().await {
+            Ok(Some(ClientMessage::Input {
+                sequence,
+                direction,
+            })) => {
+                let event = Event::Input {
+                    side,
+                    sequence,
+                    direction,
+                };
+                if events.send(event).is_err() {
+                    // match is already over
+                    return;
+                }
+            }
+            Ok(Some(ClientMessage::Ping(n))) => {
+                let _ = replies.send(ServerMessage::Pong(n));
+            }
+            Ok(Some(ClientMessage::Pong(_))) => {}
+            Ok(Some(message)) => eprintln!("{} player sent unexpected {:?}", side, message),
+            Ok(None) => break,
+            Err(e) => {
+                eprintln!("{} player: {}", side, e);
+                break;
+            }
+        }
+    }
+
+    let _ = events.send(Event::Disconnected(side));
+}
+
+async fn write_messages(
+    side: PaddleSide,
+    rules: Rules,
+    mut writer: Writer,
+    mut messages: broadcast::Receiver<ServerMessage>,
+    mut replies: mpsc::UnboundedReceiver<ServerMessage>,
+    events: mpsc::UnboundedSender<Event>,
+) {
+    if writer
+        .write_message(&ServerMessage::Welcome(side, rules))
+        .await
+        .is_err()
+    {
+        return;
+    }
+
+    loop {
+        let message = tokio::select! {
+            message = messages.recv() => match message {
+                Ok(message) => message,
+                // Points and the end of the match go out on the same
+                // channel as the snapshots, a client that missed some of
+                // them can't carry on. It's dropped as if it had left.
+                Err(broadcast::error::RecvError::Lagged(missed)) => {
+                    eprintln!("{} player fell {} messages behind", side, missed);
+                    let _ = events.send(Event::Disconnected(side));
+                    return;
+                }
+                Err(broadcast::error::RecvError::Closed) => return,
+            },
+            Some(reply) = replies.recv() => reply,
+        };
+
+        if writer.write_message(&message).await.is_err() {
+            return;
+        }
+    }
+}
@@ -0,0 +1,46 @@
+use std::{net::SocketAddr, time::Duration};
+
+use protocol::{ClientMessage, Connection, ServerMessage};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+
+use crate::ws;
+
+// Errors like running out of file descriptors don't clear up straight away,
+// the accept loops wait this long before trying again.
+pub const ACCEPT_RETRY_DELAY: Duration = Duration::from_millis(100);
+
+// A player that finished the handshake, over whichever transport it came in
+// on. Matches don't care, both carry the same messages.
+pub struct Player {
+    pub addr: SocketAddr,
+    pub reader: Reader,
+    pub writer: Writer,
+}
+
+pub enum Reader {
+    Tcp(Connection<OwnedReadHalf>),
+    Ws(ws::Reader),
+}
+
+pub enum Writer {
+    Tcp(Connection<OwnedWriteHalf>),
+    Ws(ws::Writer),
+}
+
+impl Reader {
+    pub async fn read_message(&mut self) -> protocol::Result<Option<ClientMessage>> {
+        match self {
+            Reader::Tcp(connection) => connection.read_message().await,
+            Reader::Ws(reader) => ws::read_message(reader).await,
+        }
+    }
+}
+
+impl Writer {
+    pub async fn write_message(&mut self, message: &ServerMessage) -> protocol::Result<()> {
+        match self {
+            Writer::Tcp(connection) => connection.write_message(message).await,
+            Writer::Ws(writer) => ws::write_message(writer, message).await,
+        }
+    }
+}
@@ -0,0 +1,234 @@
+// WebSocket transport, for browser clients that can't open raw TCP sockets.
+// Each binary message carries exactly one protocol payload.
+
+use std::{io, net::SocketAddr};
+
+use futures_util::{
+    SinkExt, StreamExt,
+    stream::{SplitSink, SplitStream},
+};
+use protocol::{
+    ClientMessage, MAX_FRAME_LEN, PROTOCOL_VERSION, ServerMessage, check_version, decode_payload,
+    encode_payload, expect_hello,
+};
+use tokio::{
+    net::{TcpListener, TcpStream},
+    sync::mpsc,
+    time,
+};
+use tokio_tungstenite::{
+    WebSocketStream,
+    tungstenite::{self, protocol::WebSocketConfig},
+};
+
+use crate::transport::{self, Player};
+
+pub type Reader = SplitStream<WebSocketStream<TcpStream>>;
+pub type Writer = SplitSink<WebSocketStream<TcpStream>, tungstenite::Message>;
+
+pub async fn listen(listener: TcpListener, players: mpsc::Sender<Player>) {
+    loop {
+        let (socket, peer) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                eprintln!("couldn't accept a websocket connection: {}", e);
+                time::sleep(transport::ACCEPT_RETRY_DELAY).await;
+                continue;
+            }
+        };
+        let players = players.clone();
+
+        tokio::spawn(async move {
+            match accept(socket, peer).await {
+                Ok(player) => {
+                    let _ = players.send(player).await;
+                }
+                Err(e) => eprintln!("rejected {} (websocket): {}", peer, e),
+            }
+        });
+    }
+}
+
+async fn accept(socket: TcpStream, addr: SocketAddr) -> protocol::Result<Player> {
+    // no bigger than the frames allowed over TCP, tungstenite would otherwise
+    // buffer megabytes for anyone who connects
+    let config = WebSocketConfig::default()
+        .max_message_size(Some(MAX_FRAME_LEN))
+        .max_frame_size(Some(MAX_FRAME_LEN));
+    let stream = tokio_tungstenite::accept_async_with_config(socket, Some(config))
+        .await
+        .map_err(to_protocol_error)?;
+    let (mut writer, mut reader) = stream.split();
+
+    // same handshake as over TCP, see `Connection::server_handshake`
+    let theirs = expect_hello(read_message(&mut reader).await)?;
+    write_message(
+        &mut writer,
+        &ServerMessage::Hello {
+            version: PROTOCOL_VERSION,
+        },
+    )
+    .await?;
+    check_version(theirs)?;
+
+    Ok(Player {
+        addr,
+        reader: transport::Reader::Ws(reader),
+        writer: transport::Writer::Ws(writer),
+    })
+}
+
+pub async fn read_message(reader: &mut Reader) -> protocol::Result<Option<ClientMessage>> {
+    loop {
+        match reader.next().await {
+            Some(Ok(tungstenite::Message::Binary(payload))) => {
+                return decode_payload(&payload).map(Some);
+            }
+            Some(Ok(tungstenite::Message::Close(_))) | None => return Ok(None),
+            // pings are answered by tungstenite itself
+            Some(Ok(_)) => continue,
+            Some(Err(e)) => return Err(to_protocol_error(e)),
+        }
+    }
+}
+
+pub async fn write_message(writer: &mut Writer, message: &ServerMessage) -> protocol::Result<()> {
+    let payload = encode_payload(message)?.freeze();
+    writer
+        .send(tungstenite::Message::Binary(payload))
+        .await
+        .map_err(to_protocol_error)
+}
+
+fn to_protocol_error(e: tungstenite::Error) -> protocol::Error {
+    match e {
+        tungstenite::Error::Io(e) => protocol::Error::Io(e),
+        tungstenite::Error::ConnectionClosed | tungstenite::Error::AlreadyClosed => {
+            protocol::Error::ConnectionReset
+        }
+        e => protocol::Error::Io(io::Error::other(e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use game::{PaddleSide, rules::Rules};
+    use tokio_tungstenite::MaybeTlsStream;
+
+    type Client = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+    async fn start_server() -> (SocketAddr, mpsc::Receiver<Player>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (players_tx, players) = mpsc::channel(1);
+        tokio::spawn(listen(listener, players_tx));
+        (addr, players)
+    }
+
+    async fn send(client: &mut Client, message: &ClientMessage) {
+        let payload = encode_payload(message).unwrap().freeze();
+        client
+            .send(tungstenite::Message::Binary(payload))
+            .await
+            .unwrap();
+    }
+
+    async fn recv(client: &mut Client) -> ServerMessage {
+        match client.next().await.unwrap().unwrap() {
+            tungstenite::Message::Binary(payload) => decode_payload(&payload).unwrap(),
+            other => panic!("expected a binary message, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn carries_game_messages_both_ways() {
+        let (addr, mut players) = start_server().await;
+        let (mut client, _) = tokio_tungstenite::connect_async(format!("ws://{}", addr))
+            .await
+            .unwrap();
+
+        send(
+            &mut client,
+            &ClientMessage::Hello {
+                version: PROTOCOL_VERSION,
+            },
+        )
+        .await;
+        assert_eq!(
+            recv(&mut client).await,
+            ServerMessage::Hello {
+                version: PROTOCOL_VERSION
+            }
+        );
+
+        let mut player = players.recv().await.unwrap();
+
+        player
+            .writer
+            .write_message(&ServerMessage::Welcome(PaddleSide::Right, Rules::default()))
+            .await
+            .unwrap();
+        assert_eq!(
+            recv(&mut client).await,
+            ServerMessage::Welcome(PaddleSide::Right, Rules::default())
+        );
+
+        let input = ClientMessage::Input {
+            sequence: 1,
+            direction: -0.5,
+        };
+        send(&mut client, &input).await;
+        assert_eq!(player.reader.read_message().await.unwrap(), Some(input));
+
+        client.close(None).await.unwrap();
+        assert_eq!(player.reader.read_message().await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn rejects_other_protocol_versions() {
+        let (addr, mut players) = start_server().await;
+        let (mut client, _) = tokio_tungstenite::connect_async(format!("ws://{}", addr))
+            .await
+            .unwrap();
+
+        send(
+            &mut client,
+            &ClientMessage::Hello {
+                version: PROTOCOL_VERSION + 1,
+            },
+        )
+        .await;
+        // the server still says which version it speaks before giving up
+        assert_eq!(
+            recv(&mut client).await,
+            ServerMessage::Hello {
+                version: PROTOCOL_VERSION
+            }
+        );
+        assert!(players.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn refuses_messages_bigger_than_a_frame() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut client, _) = tokio_tungstenite::connect_async(format!("ws://{}", addr))
+                .await
+                .unwrap();
+            let oversized = vec![0; MAX_FRAME_LEN + 1];
+            let _ = client
+                .send(tungstenite::Message::Binary(oversized.into()))
+                .await;
+        });
+
+        // tungstenite gives up on it before it's ever decoded as a message
+        let (socket, peer) = listener.accept().await.unwrap();
+        match accept(socket, peer).await {
+            Err(protocol::Error::Io(_)) => {}
+            Err(e) => panic!("expected an io error, got {}", e),
+            Ok(_) => panic!("expected an error"),
+        }
+    }
+}